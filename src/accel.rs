@@ -0,0 +1,191 @@
+//! `pyjit.Accel`: an acceleration structure that can be changed after it was
+//! built. Changes are collected until `refit` or `rebuild`. On the cpu backend, these
+//! only redo the bottom-level structures of the geometries that changed and the
+//! top-level structure over the instances; the GPU backends rebuild everything.
+use crate::bvh;
+use crate::context::Context;
+use crate::funcs::{self, instance_flags, AccelDesc, GeometryDesc, InstanceDesc};
+use crate::var::Var;
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple};
+use std::collections::BTreeSet;
+
+#[pyclass]
+pub struct Accel {
+    /// The description it was built from, without its instances.
+    desc: AccelDesc,
+    /// The instances by index, `None` once removed.
+    instances: Vec<Option<InstanceDesc>>,
+    /// Geometries whose vertices changed since the last build.
+    changed: BTreeSet<usize>,
+    /// Whether instances were moved, added or removed since the last build.
+    instances_changed: bool,
+    /// Whether instances were added or removed since the last build, which rules
+    /// out refitting the top-level structure.
+    instances_replaced: bool,
+    var: Var,
+}
+
+impl Accel {
+    fn instance(&mut self, instance: usize) -> PyResult<&mut Option<InstanceDesc>> {
+        match self.instances.get_mut(instance) {
+            Some(slot) if slot.is_some() => Ok(slot),
+            _ => Err(PyErr::new::<PyIndexError, _>(format!(
+                "Instance {instance} does not exist!"
+            ))),
+        }
+    }
+    /// Builds the current state. The cpu backend reuses the structures of `var` for
+    /// what did not change and refits the others if `refit` is set.
+    fn update(&mut self, refit: bool) -> PyResult<()> {
+        if self.changed.is_empty() && !self.instances_changed {
+            return Ok(());
+        }
+        let mut desc = self.desc.clone();
+        desc.instances = self.instances.iter().flatten().cloned().collect();
+        let changed = self.changed.iter().copied().collect::<Vec<_>>();
+        let var = if bvh::get(&self.var.0).is_some() {
+            let refit_instances = refit && !self.instances_replaced;
+            bvh::update(&self.var.0, &desc, &changed, refit, refit_instances)?
+        } else {
+            // rjit can only build acceleration structures from scratch, so the GPU
+            // backends rebuild everything and `refit` makes no difference there.
+            let trace = self.var.0.trace();
+            Var(desc.with_rjit(|desc| trace.accel(desc))?)
+        };
+        self.var = var;
+        self.changed.clear();
+        self.instances_changed = false;
+        self.instances_replaced = false;
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl Accel {
+    /// Builds the acceleration structure of `desc`, like `pyjit.accel`.
+    #[new]
+    #[pyo3(signature = (desc, ctx = None))]
+    pub fn new(py: Python, desc: &AccelDesc, ctx: Option<Context>) -> PyResult<Self> {
+        let var = funcs::accel(py, desc, ctx)?;
+        let mut desc = desc.clone();
+        let instances = desc.instances.drain(..).map(Some).collect();
+        Ok(Self {
+            desc,
+            instances,
+            changed: BTreeSet::new(),
+            instances_changed: false,
+            instances_replaced: false,
+            var,
+        })
+    }
+    /// The Var to trace rays against. It changes with every `refit` or `rebuild`,
+    /// earlier Vars keep the state they were built with.
+    #[getter]
+    pub fn var(&self) -> Var {
+        self.var.clone()
+    }
+    /// Calls `trace_ray` of `var`.
+    #[pyo3(signature = (*args, **kwargs))]
+    pub fn trace_ray(
+        &self,
+        py: Python,
+        args: &PyTuple,
+        kwargs: Option<&PyDict>,
+    ) -> PyResult<PyObject> {
+        self.var
+            .clone()
+            .into_py(py)
+            .call_method(py, "trace_ray", args, kwargs)
+    }
+    /// Replaces the vertices of a triangle or curve geometry, or the centers of a
+    /// sphere geometry, keeping their number.
+    pub fn update_vertices(&mut self, geometry: usize, vertices: &PyAny) -> PyResult<()> {
+        let ctx = Context(self.var.0.trace().clone());
+        let vertices = funcs::f32(vertices, None, Some(ctx))?;
+        let old = match self.desc.geometries.get_mut(geometry) {
+            Some(
+                GeometryDesc::Triangles { vertices: old, .. }
+                | GeometryDesc::Curves { vertices: old, .. }
+                | GeometryDesc::Spheres { centers: old, .. },
+            ) => old,
+            Some(GeometryDesc::Aabbs { .. }) => {
+                return Err(PyErr::new::<PyValueError, _>(format!(
+                    "Geometry {geometry} consists of AABBs, which have no vertices!"
+                )))
+            }
+            None => {
+                return Err(PyErr::new::<PyIndexError, _>(format!(
+                    "Geometry {geometry} does not exist!"
+                )))
+            }
+        };
+        if old.0.size() != vertices.0.size() {
+            return Err(PyErr::new::<PyValueError, _>(format!(
+                "Geometry {geometry} has {} vertex coordinates, got {}!",
+                old.0.size(),
+                vertices.0.size()
+            )));
+        }
+        *old = vertices;
+        self.changed.insert(geometry);
+        Ok(())
+    }
+    /// Moves an instance. Like `remove_instance`, it takes the index returned by
+    /// `add_instance`, or the position in the `AccelDesc`, not the `instance_id`.
+    pub fn set_transform(&mut self, instance: usize, transform: [f32; 12]) -> PyResult<()> {
+        self.instance(instance)?.as_mut().unwrap().transform = transform;
+        self.instances_changed = true;
+        Ok(())
+    }
+    /// Adds an instance like `AccelDesc.add_instance` and returns its index.
+    #[pyo3(signature = (geometry, transform, hit_group, visibility_mask=0xff, instance_id=None, flags=vec![], sbt_offset=0))]
+    pub fn add_instance(
+        &mut self,
+        geometry: usize,
+        transform: [f32; 12],
+        hit_group: u32,
+        visibility_mask: u8,
+        instance_id: Option<u32>,
+        flags: Vec<&str>,
+        sbt_offset: u32,
+    ) -> PyResult<usize> {
+        let idx = self.instances.len();
+        let instance = InstanceDesc {
+            geometry,
+            transform,
+            hit_group,
+            visibility_mask,
+            instance_id: instance_id.unwrap_or(idx as u32),
+            flags: instance_flags(flags)?,
+            sbt_offset,
+        };
+        self.desc.validate_instance(idx, &instance)?;
+        self.instances.push(Some(instance));
+        self.instances_changed = true;
+        self.instances_replaced = true;
+        Ok(idx)
+    }
+    /// Removes an instance, the indices of the others stay the same.
+    pub fn remove_instance(&mut self, instance: usize) -> PyResult<()> {
+        self.instance(instance)?.take();
+        self.instances_changed = true;
+        self.instances_replaced = true;
+        Ok(())
+    }
+    /// Applies the changes. The cpu backend refits the bounding volumes of the
+    /// changed geometries and of the instances, keeping their structure, which is
+    /// fast, but traversal gets slower the further things moved. It rebuilds the
+    /// top-level structure instead if instances were added or removed. The GPU
+    /// backends rebuild everything, like `rebuild`.
+    pub fn refit(&mut self) -> PyResult<()> {
+        self.update(true)
+    }
+    /// Applies the changes. The cpu backend rebuilds the structures of the changed
+    /// geometries and the top-level structure, and keeps those of unchanged
+    /// geometries. The GPU backends rebuild everything.
+    pub fn rebuild(&mut self) -> PyResult<()> {
+        self.update(false)
+    }
+}
@@ -0,0 +1,171 @@
+use crate::context::ir;
+use crate::interp;
+use once_cell::sync::OnceCell;
+use pyo3::exceptions::{PyRuntimeError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+
+/// Backends known to rjit, in the order `available_backends` reports them, followed
+/// by the reference interpreter.
+pub const BACKENDS: [&str; 3] = ["optix", "cuda", "cpu"];
+
+/// Properties of the device a backend runs on.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    #[pyo3(get)]
+    pub name: String,
+    /// Total device memory in bytes.
+    #[pyo3(get)]
+    pub memory: usize,
+    #[pyo3(get)]
+    pub compute_capability: (u32, u32),
+}
+
+#[pymethods]
+impl DeviceInfo {
+    pub fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+fn parse_backends(backend: &PyAny) -> PyResult<Vec<String>> {
+    let backends = if let Ok(backend) = backend.extract::<String>() {
+        vec![backend]
+    } else if let Ok(backends) = backend.extract::<Vec<String>>() {
+        backends
+    } else {
+        return Err(PyErr::new::<PyTypeError, _>(
+            "Expected a backend name or a list of backend names!",
+        ));
+    };
+    for backend in &backends {
+        if !BACKENDS.contains(&backend.as_str()) {
+            return Err(PyErr::new::<PyValueError, _>(format!(
+                "Unknown backend \"{backend}\", expected one of {BACKENDS:?}!"
+            )));
+        }
+    }
+    Ok(backends)
+}
+
+/// Tries the backends of `backend`, a name or a list of names, in order and returns
+/// the first one that could be initialized. A context using the cpu backend keeps
+/// it if any of its variables are alive, as their values only exist on the host.
+#[pyfunction]
+pub fn set_backend(backend: &PyAny) -> PyResult<String> {
+    let backends = parse_backends(backend)?;
+    let ir = ir();
+    let mut errors = vec![];
+    for backend in backends {
+        if backend == "cpu" {
+            interp::enable(&ir);
+            log::info!("Using the cpu backend");
+            return Ok(backend);
+        }
+        let n_variables = ir.stats().n_variables;
+        if interp::is_enabled(&ir) && n_variables > 0 {
+            errors.push(format!(
+                "{backend}: the context still holds {n_variables} variables of the cpu backend"
+            ));
+            continue;
+        }
+        match ir.set_backend(&[backend.as_str()]) {
+            Ok(()) => {
+                interp::disable(&ir);
+                log::info!("Using the {backend} backend");
+                return Ok(backend);
+            }
+            Err(err) => errors.push(format!("{backend}: {err}")),
+        }
+    }
+    Err(PyErr::new::<PyRuntimeError, _>(format!(
+        "Could not initialize any backend:\n{}",
+        errors.join("\n")
+    )))
+}
+
+/// Returns the name of the backend of the current context, if one has been set.
+#[pyfunction]
+pub fn backend() -> Option<String> {
+    if interp::is_enabled(&ir()) {
+        return Some("cpu".into());
+    }
+    ir().backend().map(|b| format!("{:?}", b).to_lowercase())
+}
+
+/// Whether the CUDA driver can be loaded and reports at least one device.
+/// The driver stays loaded afterwards, unloading it after `cuInit` would tear down
+/// the state the backends initialize again right after.
+fn has_cuda_device() -> bool {
+    static DRIVER: OnceCell<libloading::Library> = OnceCell::new();
+    let name = if cfg!(windows) {
+        "nvcuda.dll"
+    } else {
+        "libcuda.so.1"
+    };
+    // Safety: the driver API functions are called with their documented signatures.
+    unsafe {
+        let Ok(lib) = DRIVER.get_or_try_init(|| libloading::Library::new(name)) else {
+            return false;
+        };
+        let (Ok(init), Ok(device_count)) = (
+            lib.get::<unsafe extern "C" fn(u32) -> i32>(b"cuInit\0"),
+            lib.get::<unsafe extern "C" fn(*mut i32) -> i32>(b"cuDeviceGetCount\0"),
+        ) else {
+            return false;
+        };
+        let mut count = 0;
+        init(0) == 0 && device_count(&mut count) == 0 && count > 0
+    }
+}
+
+/// Whether the OptiX library shipped with the driver can be loaded.
+fn has_optix() -> bool {
+    let name = if cfg!(windows) {
+        "nvoptix.dll"
+    } else {
+        "libnvoptix.so.1"
+    };
+    // Safety: loading the library runs no code besides its initializers.
+    unsafe { libloading::Library::new(name).is_ok() }
+}
+
+/// Returns the backends that can be initialized on this machine. The driver is
+/// probed directly, without creating a trace for every backend.
+#[pyfunction]
+pub fn available_backends() -> Vec<&'static str> {
+    static AVAILABLE: OnceCell<Vec<&'static str>> = OnceCell::new();
+    AVAILABLE
+        .get_or_init(|| {
+            let cuda = has_cuda_device();
+            BACKENDS
+                .into_iter()
+                .filter(|backend| match *backend {
+                    "optix" => cuda && has_optix(),
+                    "cuda" => cuda,
+                    _ => true,
+                })
+                .collect()
+        })
+        .clone()
+}
+
+/// Returns information about the device of the current backend.
+#[pyfunction]
+pub fn device_info() -> PyResult<DeviceInfo> {
+    if interp::is_enabled(&ir()) {
+        return Ok(DeviceInfo {
+            name: "cpu (reference interpreter)".into(),
+            memory: 0,
+            compute_capability: (0, 0),
+        });
+    }
+    let info = ir()
+        .device_info()
+        .ok_or_else(|| PyErr::new::<PyRuntimeError, _>("No backend has been set!"))?;
+    Ok(DeviceInfo {
+        name: info.name,
+        memory: info.total_memory,
+        compute_capability: info.compute_capability,
+    })
+}
@@ -0,0 +1,837 @@
+//! Software ray tracing used by the "cpu" backend.
+//! `accel` builds a two-level BVH of an `AccelDesc` on the host: one tree over the
+//! primitives of each instanced geometry, in object space, and one over the
+//! instances. `update` refits or rebuilds the trees of changed geometries and
+//! shares the others with the previous BVH. `trace_ray` traverses it lane by lane.
+//! The hit groups and miss programs of the description are not run, instead the
+//! payload is filled in like the standard programs used in the tests do:
+//!
+//! - payload 0: 1 if a primitive was hit, 0 otherwise
+//! - payload 1: index of the primitive within its geometry
+//! - payload 2: `instance_id` of the instance
+//! - payload 3, 4: hit parameters `u` and `v`, as f32 bits
+//!
+//! The hit parameters are the barycentrics of the second and third vertex for
+//! triangles, the longitude and colatitude of the hit point in object space, scaled
+//! to `[0, 1]`, for spheres, and the curve parameter within the segment and 0 for
+//! curves. Other payload values, and all values of inactive lanes, are passed
+//! through. Visibility masks and triangle culling follow OptiX, other ray and
+//! instance flags have no effect since no programs are run.
+//!
+//! Curve segments are approximated by `CURVE_STEPS` capsules.
+use crate::funcs::{
+    AccelDesc, CurveKind, GeometryDesc, InstanceDesc, INSTANCE_DISABLE_CULLING,
+    INSTANCE_FLIP_WINDING,
+};
+use crate::host::HostData;
+use crate::var::Var;
+use anyhow::{anyhow, bail, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rjit::VarRef;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Acceleration structures built by `accel`, with the id of the Var standing in for
+/// them. Entries are removed by `release` when the last Var referring to them is
+/// dropped.
+static ACCELS: Lazy<Mutex<Vec<(usize, Arc<Bvh>)>>> = Lazy::new(|| Mutex::new(vec![]));
+
+/// Leaves hold at most this many primitives or instances.
+const LEAF_SIZE: usize = 4;
+/// Number of capsules approximating a non-linear curve segment.
+const CURVE_STEPS: usize = 8;
+/// Ray flags of OptiX culling triangles by their facing.
+const CULL_BACK_FACING: u32 = 1 << 4;
+const CULL_FRONT_FACING: u32 = 1 << 5;
+
+type Vec3 = [f32; 3];
+/// Row-major 3x4 affine transform, like `InstanceDesc::transform`.
+type Transform = [f32; 12];
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+/// Applies `m` to the point `p` if `w` is 1 or to the direction `p` if it is 0.
+fn apply(m: &Transform, p: Vec3, w: f32) -> Vec3 {
+    [0, 1, 2]
+        .map(|r| m[r * 4] * p[0] + m[r * 4 + 1] * p[1] + m[r * 4 + 2] * p[2] + m[r * 4 + 3] * w)
+}
+/// Inverse of `m`, `None` if it is singular.
+fn invert(m: &Transform) -> Option<Transform> {
+    let a = |r: usize, c: usize| m[r * 4 + c];
+    // Cofactors of the linear part, the signs follow from the cyclic indices.
+    let cof = |r: usize, c: usize| {
+        let (r0, r1, c0, c1) = ((r + 1) % 3, (r + 2) % 3, (c + 1) % 3, (c + 2) % 3);
+        a(r0, c0) * a(r1, c1) - a(r0, c1) * a(r1, c0)
+    };
+    let det = (0..3).map(|c| a(0, c) * cof(0, c)).sum::<f32>();
+    if det == 0. {
+        return None;
+    }
+    let mut inv = [0.; 12];
+    for r in 0..3 {
+        for c in 0..3 {
+            inv[r * 4 + c] = cof(c, r) / det;
+        }
+        inv[r * 4 + 3] = -(0..3).map(|c| inv[r * 4 + c] * a(c, 3)).sum::<f32>();
+    }
+    Some(inv)
+}
+
+/// Smallest root of `a t^2 + 2 b t + c` within `[tmin, tmax]`.
+fn quadratic(a: f32, b: f32, c: f32, tmin: f32, tmax: f32) -> Option<f32> {
+    let h = b * b - a * c;
+    if h < 0. || a == 0. {
+        return None;
+    }
+    let h = h.sqrt();
+    [(-b - h) / a, (-b + h) / a]
+        .into_iter()
+        .find(|t| (tmin..=tmax).contains(t))
+}
+
+/// The shape of a primitive in object space.
+#[derive(Clone, Copy)]
+enum Shape {
+    Triangle([Vec3; 3]),
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    /// The piece of a curve between the curve parameters `u[0]` and `u[1]`.
+    Capsule {
+        p: [Vec3; 2],
+        radius: f32,
+        u: [f32; 2],
+    },
+}
+
+/// A primitive in object space, remembering its index within the geometry.
+#[derive(Clone, Copy)]
+struct Prim {
+    shape: Shape,
+    prim_idx: u32,
+}
+
+/// One ray traced by `Bvh::intersect`.
+#[derive(Clone, Copy)]
+pub struct Ray {
+    pub o: Vec3,
+    pub d: Vec3,
+    pub tmin: f32,
+    pub tmax: f32,
+    pub vis_mask: u32,
+    pub flags: u32,
+}
+
+impl Prim {
+    fn aabb(&self) -> Aabb {
+        match self.shape {
+            Shape::Triangle(p) => Aabb::around(&p, 0.),
+            Shape::Sphere { center, radius } => Aabb::around(&[center], radius),
+            Shape::Capsule { p, radius, .. } => Aabb::around(&p, radius),
+        }
+    }
+    /// Returns `t` and the hit parameters `u` and `v` of the closest intersection
+    /// within `[ray.tmin, tmax]`, skipping triangles culled by the flags of the ray
+    /// and the instance flags `flags`.
+    fn intersect(&self, ray: &Ray, tmax: f32, flags: u32) -> Option<(f32, f32, f32)> {
+        let (o, d, tmin) = (ray.o, ray.d, ray.tmin);
+        match self.shape {
+            Shape::Triangle(p) => {
+                let (t, u, v, det) = Self::triangle(p, o, d, tmin, tmax)?;
+                let front = (det > 0.) != (flags & INSTANCE_FLIP_WINDING != 0);
+                let cull = if front {
+                    CULL_FRONT_FACING
+                } else {
+                    CULL_BACK_FACING
+                };
+                let culled = ray.flags & cull != 0 && flags & INSTANCE_DISABLE_CULLING == 0;
+                (!culled).then_some((t, u, v))
+            }
+            Shape::Sphere { center, radius } => {
+                let t = Self::sphere(center, radius, o, d, tmin, tmax)?;
+                let n = [0, 1, 2].map(|k| (o[k] + t * d[k] - center[k]) / radius);
+                let u = n[1].atan2(n[0]) / (2. * std::f32::consts::PI) + 0.5;
+                let v = n[2].clamp(-1., 1.).acos() / std::f32::consts::PI;
+                Some((t, u, v))
+            }
+            Shape::Capsule { p, radius, u } => {
+                let (t, s) = Self::capsule(p, radius, o, d, tmin, tmax)?;
+                Some((t, u[0] + (u[1] - u[0]) * s, 0.))
+            }
+        }
+    }
+    fn sphere(c: Vec3, r: f32, o: Vec3, d: Vec3, tmin: f32, tmax: f32) -> Option<f32> {
+        let oc = sub(o, c);
+        quadratic(dot(d, d), dot(oc, d), dot(oc, oc) - r * r, tmin, tmax)
+    }
+    /// Intersects the cylinder around `p[0]`, `p[1]` and the spheres capping it,
+    /// returning `t` and the position of the hit along the axis in `[0, 1]`.
+    fn capsule(p: [Vec3; 2], r: f32, o: Vec3, d: Vec3, tmin: f32, tmax: f32) -> Option<(f32, f32)> {
+        let ba = sub(p[1], p[0]);
+        let oa = sub(o, p[0]);
+        let (baba, bad, baoa) = (dot(ba, ba), dot(ba, d), dot(ba, oa));
+        let cylinder = quadratic(
+            baba * dot(d, d) - bad * bad,
+            baba * dot(oa, d) - baoa * bad,
+            baba * dot(oa, oa) - baoa * baoa - r * r * baba,
+            tmin,
+            tmax,
+        )
+        .map(|t| (t, (baoa + t * bad) / baba))
+        .filter(|(_, s)| (0. ..=1.).contains(s));
+        let caps = [0, 1].into_iter().filter_map(|i| {
+            let t = Self::sphere(p[i], r, o, d, tmin, tmax)?;
+            Some((t, i as f32))
+        });
+        cylinder
+            .into_iter()
+            .chain(caps)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+    /// Möller-Trumbore intersection, returning `t`, the barycentrics of the second
+    /// and third vertex and the determinant, which is positive for front faces.
+    fn triangle(
+        p: [Vec3; 3],
+        o: Vec3,
+        d: Vec3,
+        tmin: f32,
+        tmax: f32,
+    ) -> Option<(f32, f32, f32, f32)> {
+        let e1 = sub(p[1], p[0]);
+        let e2 = sub(p[2], p[0]);
+        let pv = cross(d, e2);
+        let det = dot(e1, pv);
+        if det == 0. {
+            return None;
+        }
+        let inv_det = 1. / det;
+        let s = sub(o, p[0]);
+        let u = dot(s, pv) * inv_det;
+        if !(0. ..=1.).contains(&u) {
+            return None;
+        }
+        let q = cross(s, e1);
+        let v = dot(d, q) * inv_det;
+        if v < 0. || u + v > 1. {
+            return None;
+        }
+        let t = dot(e2, q) * inv_det;
+        (t >= tmin && t <= tmax).then_some((t, u, v, det))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Aabb {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Aabb {
+    const EMPTY: Self = Aabb {
+        min: [f32::INFINITY; 3],
+        max: [f32::NEG_INFINITY; 3],
+    };
+    /// The box around `points`, grown by `radius`.
+    fn around(points: &[Vec3], radius: f32) -> Self {
+        let mut aabb = Self::EMPTY;
+        for p in points {
+            for axis in 0..3 {
+                aabb.min[axis] = aabb.min[axis].min(p[axis] - radius);
+                aabb.max[axis] = aabb.max[axis].max(p[axis] + radius);
+            }
+        }
+        aabb
+    }
+    fn union(self, other: Self) -> Self {
+        Aabb {
+            min: [0, 1, 2].map(|k| self.min[k].min(other.min[k])),
+            max: [0, 1, 2].map(|k| self.max[k].max(other.max[k])),
+        }
+    }
+    fn center(&self, axis: usize) -> f32 {
+        (self.min[axis] + self.max[axis]) / 2.
+    }
+    /// The box around this one after applying `m`.
+    fn transform(&self, m: &Transform) -> Self {
+        if self.min[0] > self.max[0] {
+            return Self::EMPTY;
+        }
+        let corners = (0..8)
+            .map(|i| {
+                let corner = [0, 1, 2].map(|k| {
+                    if i >> k & 1 == 0 {
+                        self.min[k]
+                    } else {
+                        self.max[k]
+                    }
+                });
+                apply(m, corner, 1.)
+            })
+            .collect::<Vec<_>>();
+        Self::around(&corners, 0.)
+    }
+    /// Slab test, returning whether the ray overlaps the box within `[tmin, tmax]`.
+    fn hit(&self, o: Vec3, inv_d: Vec3, tmin: f32, tmax: f32) -> bool {
+        let (mut t0, mut t1) = (tmin, tmax);
+        for axis in 0..3 {
+            let a = (self.min[axis] - o[axis]) * inv_d[axis];
+            let b = (self.max[axis] - o[axis]) * inv_d[axis];
+            t0 = t0.max(a.min(b));
+            t1 = t1.min(a.max(b));
+        }
+        t0 <= t1
+    }
+}
+
+/// A node covers the items `start..end`, in the order of its tree; inner nodes
+/// have two children.
+#[derive(Clone)]
+struct Node {
+    aabb: Aabb,
+    start: usize,
+    end: usize,
+    children: Option<[usize; 2]>,
+}
+
+/// Bounding volume hierarchy over items given by their boxes.
+#[derive(Clone)]
+struct Tree {
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    /// Builds the tree over the items with boxes `aabbs`, returning it with the
+    /// order of the items its nodes refer to.
+    fn build(aabbs: &[Aabb]) -> (Self, Vec<usize>) {
+        let mut order = (0..aabbs.len()).collect::<Vec<_>>();
+        let mut nodes = vec![];
+        Self::split(aabbs, &mut order, 0, &mut nodes);
+        (Self { nodes }, order)
+    }
+    /// Splits at the median center along the longest axis of the bounding box.
+    fn split(aabbs: &[Aabb], items: &mut [usize], start: usize, nodes: &mut Vec<Node>) -> usize {
+        let aabb = items
+            .iter()
+            .fold(Aabb::EMPTY, |aabb, &i| aabb.union(aabbs[i]));
+        let idx = nodes.len();
+        nodes.push(Node {
+            aabb,
+            start,
+            end: start + items.len(),
+            children: None,
+        });
+        if items.len() > LEAF_SIZE {
+            let extent = sub(aabb.max, aabb.min);
+            let axis = (0..3)
+                .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+                .unwrap();
+            let mid = items.len() / 2;
+            items.select_nth_unstable_by(mid, |&a, &b| {
+                aabbs[a].center(axis).total_cmp(&aabbs[b].center(axis))
+            });
+            let (left, right) = items.split_at_mut(mid);
+            let left = Self::split(aabbs, left, start, nodes);
+            let right = Self::split(aabbs, right, start + mid, nodes);
+            nodes[idx].children = Some([left, right]);
+        }
+        idx
+    }
+    /// Recomputes the boxes of all nodes from the new boxes of their items, given
+    /// in the order of the tree, keeping the structure.
+    fn refit(&mut self, aabbs: &[Aabb]) {
+        // Children are pushed after their parents.
+        for idx in (0..self.nodes.len()).rev() {
+            let node = &self.nodes[idx];
+            let aabb = match node.children {
+                Some([left, right]) => self.nodes[left].aabb.union(self.nodes[right].aabb),
+                None => aabbs[node.start..node.end]
+                    .iter()
+                    .fold(Aabb::EMPTY, |aabb, &other| aabb.union(other)),
+            };
+            self.nodes[idx].aabb = aabb;
+        }
+    }
+    fn aabb(&self) -> Aabb {
+        self.nodes[0].aabb
+    }
+    /// Calls `leaf` with the items of every leaf the ray overlaps within
+    /// `[tmin, tmax]`. `leaf` lowers `tmax` when it finds a closer hit.
+    fn traverse(
+        &self,
+        o: Vec3,
+        d: Vec3,
+        tmin: f32,
+        tmax: &mut f32,
+        mut leaf: impl FnMut(Range<usize>, &mut f32),
+    ) {
+        let inv_d = d.map(|x| 1. / x);
+        let mut stack = vec![0];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if !node.aabb.hit(o, inv_d, tmin, *tmax) {
+                continue;
+            }
+            match node.children {
+                Some(children) => stack.extend(children),
+                None => leaf(node.start..node.end, tmax),
+            }
+        }
+    }
+}
+
+/// The closest intersection found by `Bvh::intersect`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub t: f32,
+    pub prim_idx: u32,
+    pub instance_id: u32,
+    pub u: f32,
+    pub v: f32,
+}
+
+/// The primitives of one geometry in object space, in the order of their tree.
+struct Blas {
+    prims: Vec<Prim>,
+    /// For each entry of `prims`, its position in the order they were read in.
+    order: Vec<usize>,
+    tree: Tree,
+}
+
+impl Blas {
+    fn new(prims: Vec<Prim>) -> Self {
+        let aabbs = prims.iter().map(Prim::aabb).collect::<Vec<_>>();
+        let (tree, order) = Tree::build(&aabbs);
+        let prims = order.iter().map(|&i| prims[i]).collect();
+        Self { prims, order, tree }
+    }
+    /// Moves the primitives to `prims`, read in the same way, keeping the tree
+    /// structure.
+    fn refit(&self, prims: Vec<Prim>) -> Result<Self> {
+        if prims.len() != self.prims.len() {
+            bail!(
+                "Can not refit {} primitives to {}, rebuild instead!",
+                self.prims.len(),
+                prims.len()
+            );
+        }
+        let prims = self.order.iter().map(|&i| prims[i]).collect::<Vec<_>>();
+        let mut tree = self.tree.clone();
+        tree.refit(&prims.iter().map(Prim::aabb).collect::<Vec<_>>());
+        Ok(Self {
+            prims,
+            order: self.order.clone(),
+            tree,
+        })
+    }
+    /// Reads the primitives of `geometry`.
+    fn read(geometry: &GeometryDesc) -> Result<Vec<Prim>> {
+        let mut prims = vec![];
+        let mut push = |prim_idx: usize, shape: Shape| {
+            prims.push(Prim {
+                shape,
+                prim_idx: prim_idx as u32,
+            })
+        };
+        match geometry {
+            GeometryDesc::Triangles { vertices, indices } => {
+                let vertices = f32s(&vertices.0)?;
+                let indices = u32s(&indices.0)?;
+                let vertex = |i: u32| -> Result<Vec3> {
+                    let i = i as usize * 3;
+                    let Some(p) = vertices.get(i..i + 3) else {
+                        bail!("Vertex index {} is out of bounds!", i / 3);
+                    };
+                    Ok([p[0], p[1], p[2]])
+                };
+                for (prim_idx, tri) in indices.chunks_exact(3).enumerate() {
+                    let p = [vertex(tri[0])?, vertex(tri[1])?, vertex(tri[2])?];
+                    push(prim_idx, Shape::Triangle(p));
+                }
+            }
+            GeometryDesc::Aabbs { .. } => {
+                bail!("The cpu backend can not run the intersection programs of custom primitives!")
+            }
+            GeometryDesc::Spheres { centers, radii } => {
+                let centers = f32s(&centers.0)?;
+                let radii = f32s(&radii.0)?;
+                for (prim_idx, (c, &radius)) in centers.chunks_exact(3).zip(&radii).enumerate() {
+                    let center = [c[0], c[1], c[2]];
+                    push(prim_idx, Shape::Sphere { center, radius });
+                }
+            }
+            GeometryDesc::Curves {
+                vertices,
+                widths,
+                segment_indices,
+                kind,
+            } => {
+                let vertices = f32s(&vertices.0)?;
+                let widths = f32s(&widths.0)?;
+                let points = vertices
+                    .chunks_exact(3)
+                    .zip(widths)
+                    .map(|(p, w)| [p[0], p[1], p[2], w])
+                    .collect::<Vec<_>>();
+                let n = kind.n_control_points();
+                let steps = if *kind == CurveKind::Linear {
+                    1
+                } else {
+                    CURVE_STEPS
+                };
+                for (prim_idx, &first) in u32s(&segment_indices.0)?.iter().enumerate() {
+                    let first = first as usize;
+                    let Some(p) = points.get(first..first + n) else {
+                        bail!("Curve segment {first} is out of bounds!");
+                    };
+                    for step in 0..steps {
+                        let u = [step, step + 1].map(|i| i as f32 / steps as f32);
+                        let [a, b] = u.map(|u| curve_point(*kind, p, u));
+                        let shape = Shape::Capsule {
+                            p: [[a[0], a[1], a[2]], [b[0], b[1], b[2]]],
+                            radius: (a[3] + b[3]) / 2.,
+                            u,
+                        };
+                        push(prim_idx, shape);
+                    }
+                }
+            }
+        }
+        Ok(prims)
+    }
+    /// Returns the index and hit parameters of the closest primitive hit by the
+    /// object space `ray` within `[ray.tmin, tmax]`, lowering `tmax` to its `t`.
+    fn intersect(&self, ray: &Ray, tmax: &mut f32, flags: u32) -> Option<(u32, f32, f32)> {
+        let mut closest = None;
+        self.tree
+            .traverse(ray.o, ray.d, ray.tmin, tmax, |items, tmax| {
+                for prim in &self.prims[items] {
+                    if let Some((t, u, v)) = prim.intersect(ray, *tmax, flags) {
+                        *tmax = t;
+                        closest = Some((prim.prim_idx, u, v));
+                    }
+                }
+            });
+        closest
+    }
+}
+
+/// An instance with the inverse of its transform and its box in world space.
+#[derive(Clone)]
+struct Instance {
+    desc: InstanceDesc,
+    inv: Transform,
+    aabb: Aabb,
+}
+
+/// Two-level bounding volume hierarchy over the instances of an `AccelDesc`.
+pub struct Bvh {
+    /// One per geometry, `None` for geometries without instances.
+    blases: Vec<Option<Arc<Blas>>>,
+    /// The instances in the order of `tree`.
+    instances: Vec<Instance>,
+    /// For each entry of `instances`, its position in `AccelDesc::instances`.
+    order: Vec<usize>,
+    tree: Tree,
+}
+
+impl Bvh {
+    /// Builds the BVH of `desc`. With an `old` BVH of the same geometries, only the
+    /// geometries in `changed` are read again, refitting their trees if `refit` is
+    /// set and rebuilding them otherwise; the others are shared with `old`. The
+    /// tree over the instances is refit if `refit_instances` is set, which requires
+    /// the instances of `desc` to be those of `old`, possibly moved, and rebuilt
+    /// otherwise.
+    fn new(
+        desc: &AccelDesc,
+        old: Option<&Bvh>,
+        changed: &[usize],
+        refit: bool,
+        refit_instances: bool,
+    ) -> Result<Self> {
+        let mut blases: Vec<Option<Arc<Blas>>> = vec![None; desc.geometries.len()];
+        for (idx, instance) in desc.instances.iter().enumerate() {
+            let g = instance.geometry;
+            let Some(geometry) = desc.geometries.get(g) else {
+                bail!("Instance {idx} refers to a missing geometry!");
+            };
+            if blases[g].is_some() {
+                continue;
+            }
+            let read = || Blas::read(geometry).map_err(|err| anyhow!("Instance {idx}: {err}"));
+            let old = old.and_then(|old| old.blases.get(g).cloned().flatten());
+            blases[g] = Some(match old {
+                Some(blas) if !changed.contains(&g) => blas,
+                Some(blas) if refit => Arc::new(blas.refit(read()?)?),
+                _ => Arc::new(Blas::new(read()?)),
+            });
+        }
+        let instances = desc
+            .instances
+            .iter()
+            .enumerate()
+            .map(|(idx, instance)| {
+                let Some(inv) = invert(&instance.transform) else {
+                    bail!("Instance {idx} has a singular transform!");
+                };
+                let blas = blases[instance.geometry].as_ref().unwrap();
+                Ok(Instance {
+                    desc: instance.clone(),
+                    inv,
+                    aabb: blas.tree.aabb().transform(&instance.transform),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let (tree, order) = match old {
+            Some(old) if refit_instances && old.order.len() == instances.len() => {
+                let mut tree = old.tree.clone();
+                let aabbs = old.order.iter().map(|&i| instances[i].aabb);
+                tree.refit(&aabbs.collect::<Vec<_>>());
+                (tree, old.order.clone())
+            }
+            _ => Tree::build(&instances.iter().map(|i| i.aabb).collect::<Vec<_>>()),
+        };
+        let instances = order.iter().map(|&i| instances[i].clone()).collect();
+        Ok(Self {
+            blases,
+            instances,
+            order,
+            tree,
+        })
+    }
+    /// Returns the closest hit with `t` in `[ray.tmin, ray.tmax]` of an instance
+    /// whose visibility mask shares a bit with the one of the ray.
+    pub fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let mut closest = None;
+        let mut tmax = ray.tmax;
+        self.tree
+            .traverse(ray.o, ray.d, ray.tmin, &mut tmax, |items, tmax| {
+                for instance in &self.instances[items] {
+                    if ray.vis_mask & instance.desc.visibility_mask as u32 == 0 {
+                        continue;
+                    }
+                    let blas = self.blases[instance.desc.geometry].as_ref().unwrap();
+                    let local = Ray {
+                        o: apply(&instance.inv, ray.o, 1.),
+                        d: apply(&instance.inv, ray.d, 0.),
+                        ..*ray
+                    };
+                    if let Some((prim_idx, u, v)) =
+                        blas.intersect(&local, tmax, instance.desc.flags)
+                    {
+                        closest = Some(Hit {
+                            t: *tmax,
+                            prim_idx,
+                            instance_id: instance.desc.instance_id,
+                            u,
+                            v,
+                        });
+                    }
+                }
+            });
+        closest
+    }
+}
+
+fn f32s(var: &VarRef) -> Result<Vec<f32>> {
+    match HostData::read(var)? {
+        HostData::F32(v) => Ok(v),
+        data => bail!("Expected f32 values, got {:?}!", data.ty()),
+    }
+}
+fn u32s(var: &VarRef) -> Result<Vec<u32>> {
+    match HostData::read(var)? {
+        HostData::U32(v) => Ok(v),
+        data => bail!("Expected u32 values, got {:?}!", data.ty()),
+    }
+}
+
+/// Evaluates the curve segment with control points `p` at `s` in `[0, 1]`, returning
+/// the point and the radius there. `p` holds xyz and the radius of each point.
+fn curve_point(kind: CurveKind, p: &[[f32; 4]], s: f32) -> [f32; 4] {
+    let weights = match kind {
+        CurveKind::Linear => vec![1. - s, s],
+        CurveKind::QuadraticBSpline => vec![
+            (1. - s) * (1. - s) / 2.,
+            (-2. * s * s + 2. * s + 1.) / 2.,
+            s * s / 2.,
+        ],
+        CurveKind::CubicBSpline => vec![
+            (1. - s).powi(3) / 6.,
+            (3. * s.powi(3) - 6. * s * s + 4.) / 6.,
+            (-3. * s.powi(3) + 3. * s * s + 3. * s + 1.) / 6.,
+            s.powi(3) / 6.,
+        ],
+        CurveKind::CatmullRom => vec![
+            (-s.powi(3) + 2. * s * s - s) / 2.,
+            (3. * s.powi(3) - 5. * s * s + 2.) / 2.,
+            (-3. * s.powi(3) + 4. * s * s + s) / 2.,
+            (s.powi(3) - s * s) / 2.,
+        ],
+    };
+    let mut point = [0.; 4];
+    for (w, p) in weights.iter().zip(p) {
+        for (x, p) in point.iter_mut().zip(p) {
+            *x += w * p;
+        }
+    }
+    point
+}
+
+/// Registers `bvh` and returns a new Var standing in for it.
+fn register(bvh: Bvh) -> Result<Var> {
+    let handle = HostData::U32(vec![0]).to_var()?;
+    ACCELS.lock().push((handle.id(), Arc::new(bvh)));
+    Ok(Var(handle))
+}
+
+/// Drops the BVH the variable `id` stands in for, if any. Called when the last Var
+/// referring to it is dropped, before rjit can reuse the id.
+pub fn release(id: usize) {
+    let mut accels = ACCELS.lock();
+    if let Some(i) = accels.iter().position(|(var, _)| *var == id) {
+        accels.swap_remove(i);
+    }
+}
+
+/// Builds the BVH of `desc` on the host and returns the Var standing in for it.
+pub fn accel(desc: &AccelDesc) -> Result<Var> {
+    register(Bvh::new(desc, None, &[], false, false)?)
+}
+
+/// Builds the BVH of `desc`, which differs from the one `accel` was built from in
+/// the data of the geometries `changed` and in its instances, see `Bvh::new`.
+/// Returns a new Var standing in for it, `accel` keeps the old BVH.
+pub fn update(
+    accel: &VarRef,
+    desc: &AccelDesc,
+    changed: &[usize],
+    refit: bool,
+    refit_instances: bool,
+) -> Result<Var> {
+    let Some(old) = get(accel) else {
+        bail!("Variable {} was not built by the cpu backend!", accel.id());
+    };
+    register(Bvh::new(desc, Some(&old), changed, refit, refit_instances)?)
+}
+
+/// Returns the BVH `accel` stands in for, if it was built by `accel` above.
+pub fn get(accel: &VarRef) -> Option<Arc<Bvh>> {
+    ACCELS
+        .lock()
+        .iter()
+        .find(|(var, _)| *var == accel.id())
+        .map(|(_, bvh)| bvh.clone())
+}
+
+/// The ray operands of `trace_ray`. The visibility mask defaults to all bits and
+/// the flags to none.
+pub struct Rays<'a> {
+    pub o: [&'a VarRef; 3],
+    pub d: [&'a VarRef; 3],
+    pub tmin: &'a VarRef,
+    pub tmax: &'a VarRef,
+    pub vis_mask: Option<&'a VarRef>,
+    pub flags: Option<&'a VarRef>,
+}
+
+/// Traces one ray per lane through `bvh`, see the module documentation for how
+/// the payload is filled in. Operands have size 1 or the number of lanes. The
+/// returned payload is created in the current context.
+pub fn trace_ray(
+    bvh: &Bvh,
+    payload: &[VarRef],
+    rays: Rays,
+    mask: Option<&VarRef>,
+) -> Result<Vec<VarRef>> {
+    let o = [f32s(rays.o[0])?, f32s(rays.o[1])?, f32s(rays.o[2])?];
+    let d = [f32s(rays.d[0])?, f32s(rays.d[1])?, f32s(rays.d[2])?];
+    let (tmin, tmax) = (f32s(rays.tmin)?, f32s(rays.tmax)?);
+    let vis_mask = rays
+        .vis_mask
+        .map(u32s)
+        .transpose()?
+        .unwrap_or_else(|| vec![!0]);
+    let flags = rays.flags.map(u32s).transpose()?.unwrap_or_else(|| vec![0]);
+    let mask = match mask.map(HostData::read).transpose()? {
+        None => vec![true],
+        Some(HostData::Bool(mask)) => mask,
+        Some(data) => bail!("Expected a boolean mask, got {:?}!", data.ty()),
+    };
+    let mut payload = payload.iter().map(u32s).collect::<Result<Vec<_>>>()?;
+
+    let sizes = ["o.x", "o.y", "o.z", "d.x", "d.y", "d.z", "tmin", "tmax"]
+        .into_iter()
+        .map(String::from)
+        .zip(o.iter().chain(&d).chain([&tmin, &tmax]).map(|v| v.len()))
+        .chain([
+            ("mask".into(), mask.len()),
+            ("vis_mask".into(), vis_mask.len()),
+            ("flags".into(), flags.len()),
+        ])
+        .chain(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(i, p)| (format!("payload[{i}]"), p.len())),
+        )
+        .collect::<Vec<_>>();
+    let lanes = sizes.iter().map(|(_, len)| *len).max().unwrap_or(1);
+    for (name, len) in &sizes {
+        if *len != 1 && *len != lanes {
+            bail!("Operand {name} has {len} lanes, expected 1 or {lanes}!");
+        }
+    }
+    fn at<T: Copy>(v: &[T], i: usize) -> T {
+        v[if v.len() == 1 { 0 } else { i }]
+    }
+    for p in &mut payload {
+        if p.len() == 1 {
+            *p = vec![p[0]; lanes];
+        }
+    }
+    let set = |payload: &mut Vec<Vec<u32>>, slot: usize, lane: usize, value: u32| {
+        if let Some(p) = payload.get_mut(slot) {
+            p[lane] = value;
+        }
+    };
+    for lane in 0..lanes {
+        if !at(&mask, lane) {
+            continue;
+        }
+        let ray = Ray {
+            o: [0, 1, 2].map(|k| at(&o[k], lane)),
+            d: [0, 1, 2].map(|k| at(&d[k], lane)),
+            tmin: at(&tmin, lane),
+            tmax: at(&tmax, lane),
+            vis_mask: at(&vis_mask, lane),
+            flags: at(&flags, lane),
+        };
+        match bvh.intersect(&ray) {
+            Some(hit) => {
+                set(&mut payload, 0, lane, 1);
+                set(&mut payload, 1, lane, hit.prim_idx);
+                set(&mut payload, 2, lane, hit.instance_id);
+                set(&mut payload, 3, lane, hit.u.to_bits());
+                set(&mut payload, 4, lane, hit.v.to_bits());
+            }
+            None => set(&mut payload, 0, lane, 0),
+        }
+    }
+    payload
+        .into_iter()
+        .map(|p| HostData::U32(p).to_var())
+        .collect()
+}
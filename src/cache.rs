@@ -0,0 +1,274 @@
+use crate::funcs::IR;
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use pyo3::prelude::*;
+use rjit::Trace;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Bumped whenever the layout of cache entries or the generated code changes in an
+/// incompatible way. Entries of other versions live in sibling directories and are
+/// never read.
+const CACHE_VERSION: u32 = 1;
+const MAGIC: &[u8; 4] = b"PJKC";
+const DEFAULT_MAX_SIZE: u64 = 1 << 30;
+
+pub static CACHE: Lazy<Mutex<Option<Arc<DiskCache>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Kernel cache lookups of one trace.
+#[derive(Default)]
+pub struct Counters {
+    /// Kernels rjit did not find in its in-memory cache and looked up here. Launches
+    /// that were not looked up were in-memory hits.
+    pub lookups: AtomicU64,
+    /// Kernels loaded from, respectively missing in, the disk cache.
+    pub hits: AtomicU64,
+    pub misses: AtomicU64,
+}
+
+impl Counters {
+    pub fn reset(&self) {
+        self.lookups.store(0, Ordering::Relaxed);
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+}
+
+/// The counters of every trace the cache is attached to. Like the traces of the
+/// interpreter, they are kept until the process exits.
+static COUNTERS: Lazy<Mutex<Vec<(Trace, Arc<Counters>)>>> = Lazy::new(|| Mutex::new(vec![]));
+
+/// On-disk cache of compiled kernels, keyed by the generated code, backend and
+/// compile options.
+pub struct DiskCache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+/// 64 bit FNV-1a, which unlike `DefaultHasher` is stable across processes and
+/// compiler versions.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl DiskCache {
+    pub fn new(dir: impl Into<PathBuf>, max_size: u64) -> Result<Self> {
+        let dir = dir.into().join(format!("v{CACHE_VERSION}"));
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, max_size })
+    }
+    fn key(source: &str, backend: &str, options: &str) -> Vec<u8> {
+        [backend, options, source].join("\0").into_bytes()
+    }
+    fn path(&self, key: &[u8]) -> PathBuf {
+        self.dir.join(format!("{:016x}.bin", fnv1a(key)))
+    }
+    fn read_entry(path: &Path, key: &[u8]) -> Result<Vec<u8>> {
+        let mut file = fs::File::open(path)?;
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC || u32::from_le_bytes(header[4..8].try_into()?) != CACHE_VERSION {
+            return Err(anyhow!("Invalid cache entry {path:?}!"));
+        }
+        let key_len = u64::from_le_bytes(header[8..16].try_into()?) as usize;
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+        // Guard against hash collisions by comparing the full key.
+        if key_len > data.len() || &data[..key_len] != key {
+            return Err(anyhow!("Cache entry {path:?} does not match the kernel!"));
+        }
+        // Entries are evicted by modification time, so a hit marks them as recently used.
+        file.set_modified(SystemTime::now()).ok();
+        Ok(data.split_off(key_len))
+    }
+    fn write_entry(&self, path: &Path, key: &[u8], binary: &[u8]) -> Result<()> {
+        // Write to a temporary file first, so that concurrent processes never observe
+        // partially written entries.
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&CACHE_VERSION.to_le_bytes())?;
+        file.write_all(&(key.len() as u64).to_le_bytes())?;
+        file.write_all(key)?;
+        file.write_all(binary)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+    /// Paths of the entries, skipping temporary files other processes are writing.
+    fn entries(&self) -> Result<impl Iterator<Item = fs::DirEntry>> {
+        Ok(fs::read_dir(&self.dir)?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().map_or(false, |ext| ext == "bin")))
+    }
+    /// Removes the least recently used entries until the cache fits into `max_size`,
+    /// keeping the entry at `keep` that was just written.
+    fn evict(&self, keep: &Path) -> Result<()> {
+        let mut entries = self
+            .entries()?
+            .filter_map(|e| {
+                let meta = e.metadata().ok()?;
+                Some((meta.modified().ok()?, meta.len(), e.path()))
+            })
+            .collect::<Vec<_>>();
+        let mut size = entries.iter().map(|(_, len, _)| len).sum::<u64>();
+        entries.sort();
+        for (_, len, path) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            if path == keep {
+                continue;
+            }
+            remove(&path)?;
+            size -= len;
+        }
+        Ok(())
+    }
+    pub fn clear(&self) -> Result<()> {
+        for entry in self.entries()? {
+            remove(&entry.path())?;
+        }
+        Ok(())
+    }
+    fn load(&self, source: &str, backend: &str, options: &str) -> Option<Vec<u8>> {
+        let key = Self::key(source, backend, options);
+        let path = self.path(&key);
+        if !path.exists() {
+            return None;
+        }
+        Self::read_entry(&path, &key)
+            .map_err(|err| log::warn!("Ignoring kernel cache entry: {err}"))
+            .ok()
+    }
+    fn store(&self, source: &str, backend: &str, options: &str, binary: &[u8]) {
+        let key = Self::key(source, backend, options);
+        let path = self.path(&key);
+        let res = self
+            .write_entry(&path, &key, binary)
+            .and_then(|_| self.evict(&path));
+        if let Err(err) = res {
+            log::warn!("Could not write kernel cache entry: {err}");
+        }
+    }
+}
+
+/// Removes a cache entry, which another process may have evicted already.
+fn remove(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The cache handed to rjit, one per trace. It looks up the current disk cache on
+/// every call, so that `set_cache_dir` applies to all contexts, and counts lookups
+/// even if the disk cache is disabled.
+struct Cache(Arc<Counters>);
+
+impl rjit::KernelCache for Cache {
+    fn load(&self, source: &str, backend: &str, options: &str) -> Option<Vec<u8>> {
+        self.0.lookups.fetch_add(1, Ordering::Relaxed);
+        let disk = CACHE.lock().clone();
+        let binary = disk.and_then(|disk| disk.load(source, backend, options));
+        match binary {
+            Some(_) => self.0.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.0.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        binary
+    }
+    fn store(&self, source: &str, backend: &str, options: &str, binary: &[u8]) {
+        let disk = CACHE.lock().clone();
+        if let Some(disk) = disk {
+            disk.store(source, backend, options, binary);
+        }
+    }
+}
+
+/// Cache directory used when `set_cache_dir` has not been called: `$PYJIT_CACHE_DIR`,
+/// falling back to `$XDG_CACHE_HOME/pyjit` and `~/.cache/pyjit`.
+fn default_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("PYJIT_CACHE_DIR") {
+        return Some(dir.into());
+    }
+    if let Some(dir) = std::env::var_os("XDG_CACHE_HOME") {
+        return Some(PathBuf::from(dir).join("pyjit"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("pyjit"))
+}
+
+fn default_max_size() -> u64 {
+    std::env::var("PYJIT_CACHE_SIZE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_MAX_SIZE)
+}
+
+/// Makes `trace` use the kernel cache; called for the global and every new context.
+pub fn attach(trace: &Trace) {
+    let counters = Arc::new(Counters::default());
+    COUNTERS.lock().push((trace.clone(), counters.clone()));
+    trace.set_kernel_cache(Some(Arc::new(Cache(counters))));
+}
+
+/// The counters of `trace`.
+pub fn counters(trace: &Trace) -> Arc<Counters> {
+    COUNTERS
+        .lock()
+        .iter()
+        .find(|(t, _)| t == trace)
+        .map(|(_, counters)| counters.clone())
+        .unwrap_or_default()
+}
+
+/// Replaces the disk cache of all contexts.
+fn install(cache: Option<DiskCache>) {
+    *CACHE.lock() = cache.map(Arc::new);
+}
+
+/// Sets up the cache from the environment; called once on import.
+/// Setting `PYJIT_CACHE_DIR` to an empty string disables the cache.
+pub fn init() {
+    attach(&IR);
+    let cache = default_dir()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .and_then(|dir| match DiskCache::new(dir, default_max_size()) {
+            Ok(cache) => Some(cache),
+            Err(err) => {
+                log::warn!("Could not create kernel cache directory: {err}");
+                None
+            }
+        });
+    install(cache);
+}
+
+/// Stores compiled kernels in `path`, or disables the cache if `path` is `None`.
+/// `max_size` limits the size of the cache in bytes.
+#[pyfunction]
+pub fn set_cache_dir(path: Option<PathBuf>, max_size: Option<u64>) -> Result<()> {
+    let cache = path
+        .map(|path| DiskCache::new(path, max_size.unwrap_or_else(default_max_size)))
+        .transpose()?;
+    install(cache);
+    Ok(())
+}
+
+#[pyfunction]
+pub fn cache_dir() -> Option<PathBuf> {
+    CACHE.lock().as_ref().map(|c| c.dir.clone())
+}
+
+/// Removes all entries of the current cache version.
+#[pyfunction]
+pub fn clear_cache() -> Result<()> {
+    if let Some(cache) = CACHE.lock().as_ref() {
+        cache.clear()?;
+    }
+    Ok(())
+}
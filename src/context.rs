@@ -0,0 +1,95 @@
+use crate::backend;
+use crate::cache;
+use crate::funcs::{self, IR};
+use crate::var::Var;
+use pyo3::prelude::*;
+use rjit::Trace;
+use std::cell::RefCell;
+
+thread_local! {
+    /// Contexts entered with `with ctx:` on this thread, innermost last.
+    static STACK: RefCell<Vec<Trace>> = RefCell::new(vec![]);
+}
+
+/// Returns the trace of the innermost entered context, or the global trace if no
+/// context has been entered on this thread.
+pub fn ir() -> Trace {
+    STACK
+        .with(|stack| stack.borrow().last().cloned())
+        .unwrap_or_else(|| IR.clone())
+}
+
+/// Runs `f` with `trace` as the current trace.
+pub fn with<T>(trace: &Trace, f: impl FnOnce() -> T) -> T {
+    STACK.with(|stack| stack.borrow_mut().push(trace.clone()));
+    let res = f();
+    STACK.with(|stack| stack.borrow_mut().pop());
+    res
+}
+
+/// Runs `f` in `ctx` if one is given, otherwise in the current context.
+pub fn with_ctx<T>(ctx: Option<&Context>, f: impl FnOnce() -> T) -> T {
+    match ctx {
+        Some(ctx) => with(&ctx.0, f),
+        None => f(),
+    }
+}
+
+/// An independent JIT context with its own trace, backend and pending variables.
+/// Variables created inside `with ctx:` belong to `ctx` and can not be mixed with
+/// variables of other contexts.
+#[pyclass]
+#[derive(Clone)]
+pub struct Context(pub Trace);
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[pymethods]
+impl Context {
+    #[new]
+    pub fn new() -> Self {
+        let trace = Trace::default();
+        cache::attach(&trace);
+        Self(trace)
+    }
+    /// The global context, used outside of any `with ctx:` block.
+    #[staticmethod]
+    #[pyo3(name = "default")]
+    pub fn global_context() -> Self {
+        Self(IR.clone())
+    }
+    /// The innermost entered context.
+    #[staticmethod]
+    pub fn current() -> Self {
+        Self(ir())
+    }
+    pub fn __enter__(slf: PyRef<Self>) -> PyRef<Self> {
+        STACK.with(|stack| stack.borrow_mut().push(slf.0.clone()));
+        slf
+    }
+    pub fn __exit__(
+        &self,
+        _ty: Option<&PyAny>,
+        _value: Option<&PyAny>,
+        _traceback: Option<&PyAny>,
+    ) {
+        STACK.with(|stack| stack.borrow_mut().pop());
+    }
+    pub fn __eq__(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+
+    pub fn set_backend(&self, backend: &PyAny) -> PyResult<String> {
+        with(&self.0, || backend::set_backend(backend))
+    }
+    pub fn eval(&self, py: Python) -> PyResult<()> {
+        with(&self.0, || funcs::eval_vars(py, &[]))
+    }
+    pub fn index(&self, num: usize) -> Var {
+        with(&self.0, || funcs::index(num))
+    }
+}
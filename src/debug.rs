@@ -0,0 +1,172 @@
+use crate::context::ir;
+use crate::funcs::EVAL_LOCK;
+use crate::interp;
+use crate::var::Var;
+use anyhow::{bail, Result};
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+use std::collections::HashSet;
+
+/// A kernel that has been launched by `eval()`.
+#[pyclass]
+#[derive(Clone)]
+pub struct Kernel {
+    #[pyo3(get)]
+    pub source: String,
+    #[pyo3(get)]
+    pub size: usize,
+    #[pyo3(get)]
+    pub backend: String,
+    #[pyo3(get)]
+    pub hash: u64,
+}
+
+#[pymethods]
+impl Kernel {
+    pub fn __repr__(&self) -> String {
+        format!(
+            "Kernel(backend={}, size={}, hash={:016x})",
+            self.backend, self.size, self.hash
+        )
+    }
+}
+
+/// Returns the code `eval()` would compile for the scheduled variables and `vars`,
+/// without launching it. The set of scheduled variables is left unchanged.
+#[pyfunction]
+#[pyo3(signature = (*vars))]
+pub fn kernel_source(py: Python, vars: &PyTuple) -> Result<String> {
+    let vars = vars
+        .iter()
+        .map(|v| Ok(v.extract::<Var>()?.0.clone()))
+        .collect::<Result<Vec<_>>>()?;
+    let ir = ir();
+    if interp::is_enabled(&ir) {
+        bail!("The cpu backend interprets variables and generates no kernels!");
+    }
+    // Evals on other threads must not see the temporarily scheduled `vars`.
+    py.allow_threads(|| {
+        let _guard = EVAL_LOCK.lock();
+        let scheduled = ir.take_scheduled();
+        for var in scheduled.iter().chain(&vars) {
+            var.schedule();
+        }
+        let source = ir.kernel_source();
+        ir.take_scheduled();
+        for var in &scheduled {
+            var.schedule();
+        }
+        source
+    })
+}
+
+/// Returns the kernels launched so far, in launch order.
+#[pyfunction]
+pub fn kernel_history() -> Vec<Kernel> {
+    ir().kernel_history()
+        .into_iter()
+        .map(|k| Kernel {
+            source: k.source,
+            size: k.size,
+            backend: format!("{:?}", k.backend),
+            hash: k.hash,
+        })
+        .collect()
+}
+
+/// Escapes characters with a special meaning in graphviz record labels.
+fn escape(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| match c {
+            '{' | '}' | '|' | '<' | '>' | '"' | '\\' => vec!['\\', c],
+            _ => vec![c],
+        })
+        .collect()
+}
+
+/// Returns a graphviz DOT graph of `vars` and all variables they depend on.
+/// Evaluated variables are drawn in blue and literals in grey. Nodes show the label
+/// of their variable, which `labels` can override for the roots, in the order they
+/// were passed.
+#[pyfunction]
+#[pyo3(signature = (*vars, labels = None))]
+pub fn graphviz(vars: &PyTuple, labels: Option<Vec<String>>) -> Result<String> {
+    let roots = vars
+        .iter()
+        .map(|v| Ok(v.extract::<Var>()?.0.clone()))
+        .collect::<Result<Vec<_>>>()?;
+    let labels = labels.unwrap_or_default();
+
+    let mut visited = HashSet::new();
+    let mut stack = roots.clone();
+    let mut nodes = vec![];
+    let mut edges = vec![];
+    while let Some(var) = stack.pop() {
+        if !visited.insert(var.id()) {
+            continue;
+        }
+        // Values the cpu backend holds on the host are drawn as data, not as the
+        // placeholders standing for them in the trace.
+        let deps = if interp::holds(&var) {
+            vec![]
+        } else {
+            var.deps()
+        };
+        for (i, dep) in deps.into_iter().enumerate() {
+            edges.push(format!(
+                "    {} -> {} [label=\"{}\"];",
+                dep.id(),
+                var.id(),
+                i
+            ));
+            stack.push(dep);
+        }
+        nodes.push(var);
+    }
+    nodes.sort_by_key(|v| v.id());
+
+    let mut dot = String::from("digraph {\n    rankdir=BT;\n");
+    dot += "    node [shape=record fontname=Consolas style=filled fillcolor=white];\n";
+    for var in &nodes {
+        let on_host = interp::holds(var);
+        let op = if on_host {
+            "Data".into()
+        } else {
+            format!("{:?}", var.op())
+        };
+        let mut fields = vec![
+            format!("#{}", var.id()),
+            escape(&op),
+            format!("{:?}[{}]", var.ty(), var.size()),
+        ];
+        let label = roots
+            .iter()
+            .position(|r| r.id() == var.id())
+            .and_then(|i| labels.get(i).cloned())
+            .or_else(|| var.label());
+        if let Some(label) = label {
+            fields.insert(0, escape(&label));
+        }
+        let color = if var.is_evaluated() || on_host {
+            fields.push("evaluated".into());
+            "lightblue"
+        } else if var.is_literal() {
+            fields.push("literal".into());
+            "lightgrey"
+        } else {
+            "white"
+        };
+        dot += &format!(
+            "    {} [label=\"{{{}}}\" fillcolor={}];\n",
+            var.id(),
+            fields.join("|"),
+            color
+        );
+    }
+    for edge in edges.iter().rev() {
+        dot += edge;
+        dot += "\n";
+    }
+    dot += "}\n";
+    Ok(dot)
+}
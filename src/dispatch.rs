@@ -0,0 +1,152 @@
+use crate::funcs;
+use crate::tree;
+use crate::var::Var;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+use rjit::{VarRef, VarType};
+use std::cell::RefCell;
+
+/// Instances that can be addressed by `dispatch`.
+/// Id 0 is reserved as a null pointer, so slot `i` holds the instance with id `i + 1`.
+static REGISTRY: Lazy<Mutex<Vec<Option<PyObject>>>> = Lazy::new(|| Mutex::new(vec![]));
+
+thread_local! {
+    /// Lanes the callees being traced on this thread are called for, innermost
+    /// last. Each entry already includes the ones before it.
+    static MASKS: RefCell<Vec<VarRef>> = RefCell::new(vec![]);
+}
+
+/// Runs `f` with side effects restricted to the lanes of `mask`, and of any
+/// enclosing call.
+fn with_mask<T>(mask: &VarRef, f: impl FnOnce() -> T) -> PyResult<T> {
+    let mask = masked(Some(mask))?.unwrap();
+    MASKS.with(|masks| masks.borrow_mut().push(mask));
+    let res = f();
+    MASKS.with(|masks| masks.borrow_mut().pop());
+    Ok(res)
+}
+
+/// Combines the mask of a side effect with the lanes of the callees being traced,
+/// so that a scatter in a callee only writes the lanes it is called for.
+pub fn masked(mask: Option<&VarRef>) -> PyResult<Option<VarRef>> {
+    let Some(active) = MASKS.with(|masks| masks.borrow().last().cloned()) else {
+        return Ok(mask.cloned());
+    };
+    Ok(Some(match mask {
+        Some(mask) => mask.and(&active)?,
+        None => active,
+    }))
+}
+
+/// Registers a python object and returns its id, to be stored in a `u32` Var and
+/// passed to `dispatch`.
+#[pyfunction]
+pub fn register(obj: PyObject) -> u32 {
+    let mut registry = REGISTRY.lock();
+    if let Some(slot) = registry.iter().position(|o| o.is_none()) {
+        registry[slot] = Some(obj);
+        slot as u32 + 1
+    } else {
+        registry.push(Some(obj));
+        registry.len() as u32
+    }
+}
+
+#[pyfunction]
+pub fn unregister(id: u32) -> PyResult<()> {
+    let mut registry = REGISTRY.lock();
+    match registry.get_mut((id as usize).wrapping_sub(1)) {
+        Some(slot @ Some(_)) => {
+            *slot = None;
+            Ok(())
+        }
+        _ => Err(PyErr::new::<PyValueError, _>(format!(
+            "No instance with id {id} is registered!"
+        ))),
+    }
+}
+
+/// `obj` with its Vars replaced by zeros, the result of lanes no callee is selected
+/// for.
+fn zeros(py: Python, obj: &PyAny) -> PyResult<PyObject> {
+    tree::map(py, &[obj], &mut |leaves| match leaves[0].extract::<Var>() {
+        Ok(var) if var.0.ty() == VarType::Bool => Ok(false.into_py(py)),
+        Ok(_) => Ok(0.into_py(py)),
+        Err(_) => Ok(leaves[0].into()),
+    })
+}
+
+/// Traces every callee exactly once with `args` and selects, per lane, the result
+/// of the callee with id `index[lane]`, or zero if there is none. Scatters of a
+/// callee only write the lanes whose index selects it.
+///
+/// rjit has no indirect calls, so every callee runs on all lanes of the resulting
+/// kernel and the cost grows with the number of callees.
+fn call_each(
+    py: Python,
+    index: &Var,
+    callees: &[(u32, PyObject)],
+    args: &PyTuple,
+) -> PyResult<PyObject> {
+    let mut res: Option<PyObject> = None;
+    for (id, callee) in callees {
+        let mask = index.eq(id.into_py(py).as_ref(py))?;
+        let out = with_mask(&mask.0, || callee.call1(py, args))??;
+        let prev = match res {
+            Some(prev) => prev,
+            None => zeros(py, out.as_ref(py))?,
+        };
+        let mask = mask.into_py(py);
+        res = Some(tree::select(
+            py,
+            mask.as_ref(py),
+            out.as_ref(py),
+            prev.as_ref(py),
+        )?);
+    }
+    Ok(res.unwrap_or_else(|| py.None()))
+}
+
+/// Calls `fns[index[lane]]` for every lane.
+/// Each function is traced once with the full-width arguments and the results are
+/// combined into a single kernel, so no `compress`/`gather` passes are needed.
+/// This is not an indirect call: every function is executed for all lanes, so the
+/// cost is that of all `fns` together. Scatters only write the lanes that select
+/// their function. Lanes with an out of range index get zeros and run no side
+/// effects.
+#[pyfunction]
+#[pyo3(signature = (index, fns, *args))]
+pub fn switch(py: Python, index: &PyAny, fns: Vec<PyObject>, args: &PyTuple) -> PyResult<PyObject> {
+    let index = funcs::u32(index, None, None)?;
+    let callees = fns
+        .into_iter()
+        .enumerate()
+        .map(|(i, f)| (i as u32, f))
+        .collect::<Vec<_>>();
+    call_each(py, &index, &callees, args)
+}
+
+/// Calls `method` on the registered instance with id `ptr[lane]` for every lane.
+/// Every registered instance is traced once and executed for all lanes, so the cost
+/// grows with the number of registered instances, whichever ids `ptr` holds. Their
+/// scatters are restricted to the lanes holding their id. Lanes holding an unknown
+/// id get zeros and run no side effects.
+#[pyfunction]
+#[pyo3(signature = (ptr, method, *args))]
+pub fn dispatch(py: Python, ptr: &PyAny, method: &str, args: &PyTuple) -> PyResult<PyObject> {
+    let ptr = funcs::u32(ptr, None, None)?;
+    let callees = REGISTRY
+        .lock()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, o)| Some((i as u32 + 1, o.as_ref()?.clone_ref(py))))
+        .collect::<Vec<_>>();
+    let callees = callees
+        .into_iter()
+        .map(|(id, obj)| Ok((id, obj.getattr(py, method)?)))
+        .collect::<PyResult<Vec<_>>>()?;
+    call_each(py, &ptr, &callees, args)
+}
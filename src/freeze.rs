@@ -0,0 +1,403 @@
+//! `@pyjit.freeze`: records the trace a function builds on its first call and
+//! replays it for later calls with inputs of the same layout, skipping the python
+//! code of the function.
+use crate::context;
+use crate::interp;
+use crate::record::Recording;
+use crate::tree;
+use crate::var::Var;
+use parking_lot::Mutex;
+use pyo3::exceptions::{PyRuntimeError, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyList, PyLong, PyString, PyTuple};
+use rjit::{Op, VarRef, VarType};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Where a replayed input comes from.
+enum Source {
+    /// The Var at this position among the Vars of the arguments.
+    Arg(usize),
+    /// The Var at position `leaf` among the Vars of something the function
+    /// captured. It is looked up again on every call, so that rebinding a global or
+    /// a closure variable takes effect.
+    Captured {
+        capture: Capture,
+        leaf: usize,
+        ty: VarType,
+        size: usize,
+    },
+}
+
+/// A variable captured by the frozen function.
+#[derive(Clone)]
+enum Capture {
+    /// A global the function refers to by this name.
+    Global(String),
+    /// The closure cell at this position.
+    Cell(usize),
+    /// The attributes of the object a frozen method is bound to.
+    Bound,
+}
+
+impl Capture {
+    /// All variables `func` captures with their values, including the attributes of
+    /// `bound`. Unset closure cells are skipped.
+    fn all<'py>(
+        func: &'py PyAny,
+        bound: Option<&'py PyAny>,
+    ) -> PyResult<Vec<(Capture, &'py PyAny)>> {
+        let mut captures = vec![];
+        if let Some(bound) = bound {
+            captures.push((Capture::Bound, Self::attributes(bound)));
+        }
+        if let (Ok(code), Ok(globals)) = (func.getattr("__code__"), func.getattr("__globals__")) {
+            let globals = globals.downcast::<PyDict>()?;
+            for name in code.getattr("co_names")?.iter()? {
+                let name = name?.extract::<String>()?;
+                if let Some(value) = globals.get_item(name.as_str()) {
+                    captures.push((Capture::Global(name), value));
+                }
+            }
+        }
+        if let Ok(closure) = func.getattr("__closure__") {
+            if !closure.is_none() {
+                for (i, cell) in closure.iter()?.enumerate() {
+                    if let Ok(value) = cell?.getattr("cell_contents") {
+                        captures.push((Capture::Cell(i), value));
+                    }
+                }
+            }
+        }
+        Ok(captures)
+    }
+    /// The attributes of `obj`, or `obj` itself if it has no `__dict__`, like an
+    /// instance of a class with `__slots__`.
+    fn attributes(obj: &PyAny) -> &PyAny {
+        obj.getattr("__dict__").unwrap_or(obj)
+    }
+    /// The current value of the captured variable, if it is still bound.
+    fn get<'py>(&self, func: &'py PyAny, bound: Option<&'py PyAny>) -> Option<&'py PyAny> {
+        match self {
+            Capture::Bound => bound.map(Self::attributes),
+            Capture::Global(name) => func
+                .getattr("__globals__")
+                .ok()?
+                .downcast::<PyDict>()
+                .ok()?
+                .get_item(name.as_str()),
+            Capture::Cell(i) => func
+                .getattr("__closure__")
+                .ok()?
+                .get_item(*i)
+                .ok()?
+                .getattr("cell_contents")
+                .ok(),
+        }
+    }
+}
+
+struct Frame {
+    recording: Recording,
+    sources: Vec<Source>,
+    /// The return value of the recorded call, whose Vars are replaced on replay.
+    output: PyObject,
+}
+
+/// Appends a description of the layout of `obj` to `key`: the structure of its
+/// containers and the type and size of its Vars. `None`, bools, ints and strings
+/// are keyed by value, as they usually select what the function does. Other values
+/// are rejected, keying them by value would make a recording for every call.
+fn layout(py: Python, obj: &PyAny, key: &mut String) -> PyResult<()> {
+    if let Ok(var) = obj.extract::<Var>() {
+        write!(key, "{:?}[{}]", var.0.ty(), var.0.size()).unwrap();
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        key.push('{');
+        for (k, v) in dict {
+            write!(key, "{}:", k.repr()?).unwrap();
+            layout(py, v, key)?;
+            key.push(',');
+        }
+        key.push('}');
+    } else if obj.is_instance_of::<PyList>()? || obj.is_instance_of::<PyTuple>()? {
+        key.push('[');
+        for item in obj.iter()? {
+            layout(py, item?, key)?;
+            key.push(',');
+        }
+        key.push(']');
+    } else if let Some(fields) = tree::fields(py, obj)? {
+        write!(key, "{}(", obj.get_type().name()?).unwrap();
+        for field in fields {
+            write!(key, "{field}=").unwrap();
+            layout(py, obj.getattr(field.as_str())?, key)?;
+            key.push(',');
+        }
+        key.push(')');
+    } else if obj.is_none()
+        || obj.is_instance_of::<PyBool>()?
+        || obj.is_instance_of::<PyLong>()?
+        || obj.is_instance_of::<PyString>()?
+    {
+        write!(key, "{}:{}", obj.get_type().name()?, obj.repr()?).unwrap();
+    } else {
+        return Err(PyErr::new::<PyTypeError, _>(format!(
+            "Frozen functions can not take a {}, pass Vars, containers or dataclasses of \
+             them, bools, ints, strings or None!",
+            obj.get_type().name()?
+        )));
+    }
+    Ok(())
+}
+
+thread_local! {
+    /// Number of scatters made by each frozen function being recorded on this
+    /// thread, innermost last.
+    static SCATTERS: RefCell<Vec<usize>> = RefCell::new(vec![]);
+}
+
+/// Notes a scatter for the frozen function being recorded, if any. Replays only
+/// rebuild the variables the function returns, so they would lose the scatter.
+pub fn scatter() {
+    SCATTERS.with(|scatters| {
+        if let Some(n) = scatters.borrow_mut().last_mut() {
+            *n += 1;
+        }
+    });
+}
+
+/// A function wrapped by `freeze`.
+#[pyclass]
+pub struct Frozen {
+    func: PyObject,
+    /// The object a frozen method is bound to. It is passed as the first argument,
+    /// but is not part of the layout, and its Vars are looked up again on every
+    /// call like captured variables.
+    bound: Option<PyObject>,
+    /// Recordings by the layout of the arguments they were made with, shared by
+    /// the bound methods of all objects.
+    frames: Arc<Mutex<HashMap<String, Arc<Frame>>>>,
+}
+
+impl Frozen {
+    fn bound<'py>(&'py self, py: Python<'py>) -> Option<&'py PyAny> {
+        self.bound.as_ref().map(|bound| bound.as_ref(py))
+    }
+    /// Calls the function, passing the bound object first.
+    fn call(&self, py: Python, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<PyObject> {
+        match self.bound(py) {
+            Some(bound) => {
+                let args = std::iter::once(bound).chain(args).collect::<Vec<_>>();
+                let args = PyTuple::new(py, args);
+                self.func.call(py, args, kwargs)
+            }
+            None => self.func.call(py, args, kwargs),
+        }
+    }
+    fn record(&self, py: Python, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<Frame> {
+        let inputs = Self::leaves(py, args, kwargs)?;
+        SCATTERS.with(|scatters| scatters.borrow_mut().push(0));
+        let output = self.call(py, args, kwargs);
+        let n_scatters = SCATTERS.with(|scatters| scatters.borrow_mut().pop().unwrap());
+        let output = output?;
+        if n_scatters > 0 {
+            return Err(PyErr::new::<PyRuntimeError, _>(
+                "Can not freeze functions that scatter, replays would skip the scatter!",
+            ));
+        }
+        let mut outputs = vec![];
+        tree::leaves(py, output.as_ref(py), &mut outputs)?;
+        let outputs = outputs.into_iter().map(|v| v.0.clone()).collect::<Vec<_>>();
+        let (recording, vars) = Recording::with_inputs(&outputs, &inputs, false)
+            .map_err(|err| PyErr::new::<PyRuntimeError, _>(format!("Can not freeze: {err}")))?;
+        let captures = Capture::all(self.func.as_ref(py), self.bound(py))?
+            .into_iter()
+            .map(|(capture, value)| {
+                let mut leaves = vec![];
+                tree::leaves(py, value, &mut leaves)?;
+                Ok((capture, leaves))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let sources = vars
+            .into_iter()
+            .map(|var| {
+                if let Some(i) = inputs.iter().position(|input| input.id() == var.id()) {
+                    return Ok(Source::Arg(i));
+                }
+                // An evaluated intermediate would be replayed with its old contents.
+                if !matches!(var.op(), Op::Data) && !interp::is_upload(&var) {
+                    return Err(PyErr::new::<PyRuntimeError, _>(
+                        "Can not freeze functions that evaluate variables!",
+                    ));
+                }
+                captures
+                    .iter()
+                    .find_map(|(capture, leaves)| {
+                        let leaf = leaves.iter().position(|v| v.0.id() == var.id())?;
+                        Some(Source::Captured {
+                            capture: capture.clone(),
+                            leaf,
+                            ty: var.ty(),
+                            size: var.size(),
+                        })
+                    })
+                    .ok_or_else(|| {
+                        PyErr::new::<PyRuntimeError, _>(
+                            "Can not freeze: the function uses a variable that is neither an \
+                             argument, nor a global or closure variable!",
+                        )
+                    })
+            })
+            .collect::<PyResult<_>>()?;
+        Ok(Frame {
+            recording,
+            sources,
+            output,
+        })
+    }
+    /// The inputs to replay `frame` with, or `None` if a captured variable was
+    /// rebound to something of another layout, which needs a new recording.
+    fn inputs(&self, py: Python, frame: &Frame, args: &[VarRef]) -> PyResult<Option<Vec<VarRef>>> {
+        let func = self.func.as_ref(py);
+        let mut inputs = Vec::with_capacity(frame.sources.len());
+        for source in &frame.sources {
+            let var = match source {
+                Source::Arg(i) => args[*i].clone(),
+                Source::Captured {
+                    capture,
+                    leaf,
+                    ty,
+                    size,
+                } => {
+                    let Some(value) = capture.get(func, self.bound(py)) else {
+                        return Ok(None);
+                    };
+                    let mut leaves = vec![];
+                    tree::leaves(py, value, &mut leaves)?;
+                    match leaves.get(*leaf) {
+                        Some(var) if var.0.ty() == *ty && var.0.size() == *size => var.0.clone(),
+                        _ => return Ok(None),
+                    }
+                }
+            };
+            inputs.push(var);
+        }
+        Ok(Some(inputs))
+    }
+    /// Replays the recording made for `key`, if there is one and it still applies.
+    /// The trace is rebuilt from the recording without running the function; its
+    /// kernel generates the same code as the recorded one, so rjit launches the
+    /// kernel it compiled before instead of compiling it again.
+    fn replay(
+        &self,
+        py: Python,
+        key: &str,
+        args: &PyTuple,
+        kwargs: Option<&PyDict>,
+    ) -> PyResult<Option<PyObject>> {
+        // The lock is not held while looking up captured variables, which runs python
+        // code, or while replaying.
+        let Some(frame) = self.frames.lock().get(key).cloned() else {
+            return Ok(None);
+        };
+        let args = Self::leaves(py, args, kwargs)?;
+        let Some(inputs) = self.inputs(py, &frame, &args)? else {
+            return Ok(None);
+        };
+        let trace = args
+            .first()
+            .map(|v| v.trace().clone())
+            .unwrap_or_else(context::ir);
+        let mut outputs =
+            context::with(&trace, || frame.recording.replay_with(&inputs))?.into_iter();
+        let output = tree::map(
+            py,
+            &[frame.output.as_ref(py)],
+            &mut |leaves| match leaves[0].extract::<Var>() {
+                Ok(_) => Ok(Var(outputs.next().unwrap()).into_py(py)),
+                Err(_) => Ok(leaves[0].into()),
+            },
+        )?;
+        Ok(Some(output))
+    }
+    fn leaves(py: Python, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<Vec<VarRef>> {
+        let mut vars = vec![];
+        tree::leaves(py, args, &mut vars)?;
+        if let Some(kwargs) = kwargs {
+            tree::leaves(py, kwargs, &mut vars)?;
+        }
+        Ok(vars.into_iter().map(|v| v.0.clone()).collect())
+    }
+}
+
+#[pymethods]
+impl Frozen {
+    #[pyo3(signature = (*args, **kwargs))]
+    pub fn __call__(
+        &self,
+        py: Python,
+        args: &PyTuple,
+        kwargs: Option<&PyDict>,
+    ) -> PyResult<PyObject> {
+        let mut key = String::new();
+        layout(py, args, &mut key)?;
+        if let Some(kwargs) = kwargs {
+            layout(py, kwargs, &mut key)?;
+        }
+        if let Some(output) = self.replay(py, &key, args, kwargs)? {
+            return Ok(output);
+        }
+        // Record without holding the lock, the function may call itself.
+        let frame = self.record(py, args, kwargs)?;
+        let output = frame.output.clone_ref(py);
+        self.frames.lock().insert(key, Arc::new(frame));
+        Ok(output)
+    }
+    /// Binds the frozen function to `obj` when used as a method.
+    pub fn __get__(
+        slf: Py<Self>,
+        py: Python,
+        obj: Option<&PyAny>,
+        _ty: Option<&PyAny>,
+    ) -> PyResult<PyObject> {
+        let Some(obj) = obj else {
+            return Ok(slf.into_py(py));
+        };
+        let frozen = slf.borrow(py);
+        let bound = Frozen {
+            func: frozen.func.clone_ref(py),
+            bound: Some(obj.into()),
+            frames: frozen.frames.clone(),
+        };
+        Ok(Py::new(py, bound)?.into_py(py))
+    }
+    /// Number of recordings made so far, one per distinct argument layout.
+    #[getter]
+    pub fn n_recordings(&self) -> usize {
+        self.frames.lock().len()
+    }
+    /// Drops all recordings, so the next call records again.
+    pub fn clear(&self) {
+        self.frames.lock().clear();
+    }
+}
+
+/// Decorator recording the trace a function builds on its first call. Later calls
+/// whose arguments have the same structure, Var types and sizes and the same
+/// `None`, bool, int and string values rebuild that trace directly instead of
+/// running the function, then return its result with the new Vars. A new recording
+/// is made whenever the layout of the arguments or of a captured Var changes.
+///
+/// The function must only build variables from its arguments, the globals and
+/// closure variables it captures and, for methods, the attributes of the object;
+/// it must not evaluate them or scatter, which raises when recording.
+#[pyfunction]
+pub fn freeze(func: PyObject) -> Frozen {
+    Frozen {
+        func,
+        bound: None,
+        frames: Arc::new(Mutex::new(HashMap::new())),
+    }
+}
@@ -1,62 +1,333 @@
+use super::bvh;
+use super::context::{self, ir, Context};
+use super::host::HostData;
+use super::interp;
+use super::logging;
+use super::profile;
+use super::tree;
 use super::var::Var;
 use anyhow::Result;
 use once_cell::sync::Lazy;
-use pyo3::exceptions::PyTypeError;
+use parking_lot::Mutex;
+use pyo3::exceptions::{PyTypeError, PyValueError};
 use pyo3::prelude::*;
-use rjit::Trace;
+use pyo3::types::PyTuple;
+use rjit::{Trace, VarRef};
 
+/// The trace of the global context, see `context::ir` for the current one.
+/// Being a static, it requires `Trace: Sync`: rjit locks the state of a trace for
+/// every operation, so variables can be created from multiple python threads.
 pub static IR: Lazy<Trace> = Lazy::new(|| Trace::default());
 
-#[pyfunction]
-pub fn set_backend(backend: &str) -> Result<()> {
-    IR.set_backend(&[backend])
+/// Held for the duration of every eval and by everything else that takes variables
+/// out of the schedule. rjit locks a trace per operation only, so without it
+/// concurrent evals could interleave, and the kernels read back for logging and
+/// profiling could belong to another eval. It must not be taken while holding the
+/// GIL, as evals take the GIL to log.
+pub static EVAL_LOCK: Mutex<()> = parking_lot::const_mutex(());
+
+/// Evaluates `vars` and their dependencies on `ir`, leaving unrelated scheduled
+/// variables pending. Evaluates all scheduled variables if `vars` is empty.
+/// Contexts using the cpu backend are evaluated by the reference interpreter.
+/// Launched kernels are logged, which briefly takes the GIL, so it must not be called
+/// while holding the GIL.
+pub fn eval_on(ir: &Trace, vars: &[VarRef]) -> Result<()> {
+    let _guard = EVAL_LOCK.lock();
+    if let Some(res) = interp::eval(ir, vars) {
+        return res;
+    }
+    let n_kernels = ir.kernel_history_len();
+    profile::record_eval(ir, || {
+        if vars.is_empty() {
+            ir.eval()
+        } else {
+            ir.eval_vars(&vars.iter().collect::<Vec<_>>())
+        }
+    });
+    logging::log_launches(ir, n_kernels);
+    Ok(())
 }
 
+/// Evaluates `vars`, or all scheduled variables of the current context if `vars` is
+/// empty. The GIL is released while kernels are compiled and run.
+pub fn eval_vars(py: Python, vars: &[VarRef]) -> PyResult<()> {
+    let ir = vars.first().map(|v| v.trace().clone()).unwrap_or_else(ir);
+    py.allow_threads(|| eval_on(&ir, vars))?;
+    Ok(())
+}
+
+/// `eval()` evaluates all scheduled variables, `eval(*objs)` only the Vars contained
+/// in `objs` and the variables they depend on. `objs` can be Vars or nested lists,
+/// tuples, dicts and dataclasses of them.
 #[pyfunction]
-pub fn eval() {
-    IR.eval();
+#[pyo3(signature = (*objs))]
+pub fn eval(py: Python, objs: &PyTuple) -> PyResult<()> {
+    let mut vars = vec![];
+    tree::leaves(py, objs, &mut vars)?;
+    if vars.is_empty() && !objs.is_empty() {
+        return Ok(());
+    }
+    eval_vars(
+        py,
+        &vars.into_iter().map(|v| v.0.clone()).collect::<Vec<_>>(),
+    )
 }
 
 #[pyfunction]
 pub fn index(num: usize) -> Var {
-    Var(IR.index(num))
+    Var(ir().index(num))
 }
 
+/// Creates a texture in `ctx`, or the current context.
 #[pyfunction]
-pub fn texture(shape: Vec<usize>, n_channels: usize) -> Result<Var> {
-    Ok(Var(IR.texture(&shape, n_channels)?))
+#[pyo3(signature = (shape, n_channels, ctx = None))]
+pub fn texture(shape: Vec<usize>, n_channels: usize, ctx: Option<Context>) -> Result<Var> {
+    context::with_ctx(ctx.as_ref(), || Ok(Var(ir().texture(&shape, n_channels)?)))
+}
+
+#[derive(Clone)]
+pub enum GeometryDesc {
+    Triangles {
+        vertices: Var,
+        indices: Var,
+    },
+    /// Custom primitives given by their bounding boxes, intersected by the
+    /// intersection program of the instance's hit group.
+    Aabbs {
+        bbox_min: Var,
+        bbox_max: Var,
+    },
+    /// Spheres given by their centers as xyz triples and their radii.
+    Spheres {
+        centers: Var,
+        radii: Var,
+    },
+    /// Curves through the control points `vertices`, given as xyz triples with one
+    /// width each. Every segment starts at the control point in `segment_indices`
+    /// and spans `kind.n_control_points()` points.
+    Curves {
+        vertices: Var,
+        widths: Var,
+        segment_indices: Var,
+        kind: CurveKind,
+    },
+}
+
+/// The basis of the segments of `GeometryDesc::Curves`. The discriminants are part
+/// of the saved format, see `GeometryDesc::kind`, and must not change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CurveKind {
+    Linear = 0,
+    QuadraticBSpline = 1,
+    CubicBSpline = 2,
+    CatmullRom = 3,
+}
+
+impl CurveKind {
+    pub const ALL: [Self; 4] = [
+        Self::Linear,
+        Self::QuadraticBSpline,
+        Self::CubicBSpline,
+        Self::CatmullRom,
+    ];
+    /// The name used by `add_curves`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Linear => "linear",
+            Self::QuadraticBSpline => "quadratic",
+            Self::CubicBSpline => "cubic",
+            Self::CatmullRom => "catmull_rom",
+        }
+    }
+    pub fn from_name(name: &str) -> PyResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| {
+                PyErr::new::<PyValueError, _>(format!(
+                    "Unknown curve kind {name:?}, expected one of {:?}!",
+                    Self::ALL.map(Self::name)
+                ))
+            })
+    }
+    /// Number of control points of one segment.
+    pub fn n_control_points(self) -> usize {
+        match self {
+            Self::Linear => 2,
+            Self::QuadraticBSpline => 3,
+            Self::CubicBSpline | Self::CatmullRom => 4,
+        }
+    }
 }
 
-enum GeometryDesc {
-    Triangles { vertices: Var, indices: Var },
+impl From<CurveKind> for rjit::CurveKind {
+    fn from(kind: CurveKind) -> Self {
+        match kind {
+            CurveKind::Linear => Self::Linear,
+            CurveKind::QuadraticBSpline => Self::QuadraticBSpline,
+            CurveKind::CubicBSpline => Self::CubicBSpline,
+            CurveKind::CatmullRom => Self::CatmullRom,
+        }
+    }
 }
-struct InstanceDesc {
+
+impl GeometryDesc {
+    /// Code of the variant in saved files.
+    pub fn kind(&self) -> u8 {
+        match self {
+            Self::Triangles { .. } => 0,
+            Self::Aabbs { .. } => 1,
+            Self::Spheres { .. } => 2,
+            Self::Curves { kind, .. } => 3 + *kind as u8,
+        }
+    }
+    /// Describes the primitives for error messages.
+    pub fn name(&self) -> String {
+        match self {
+            Self::Triangles { .. } => "triangles".into(),
+            Self::Aabbs { .. } => "AABBs".into(),
+            Self::Spheres { .. } => "spheres".into(),
+            Self::Curves { kind, .. } => format!("{} curves", kind.name()),
+        }
+    }
+    /// The Vars of the variant, in the order of its fields.
+    pub fn vars(&self) -> Vec<&Var> {
+        match self {
+            Self::Triangles { vertices, indices } => vec![vertices, indices],
+            Self::Aabbs { bbox_min, bbox_max } => vec![bbox_min, bbox_max],
+            Self::Spheres { centers, radii } => vec![centers, radii],
+            Self::Curves {
+                vertices,
+                widths,
+                segment_indices,
+                ..
+            } => vec![vertices, widths, segment_indices],
+        }
+    }
+    /// Inverse of `kind` and `vars`.
+    pub fn from_vars(kind: u8, vars: Vec<Var>) -> Option<Self> {
+        let mut vars = vars.into_iter();
+        let mut next = || vars.next();
+        Some(match kind {
+            0 => Self::Triangles {
+                vertices: next()?,
+                indices: next()?,
+            },
+            1 => Self::Aabbs {
+                bbox_min: next()?,
+                bbox_max: next()?,
+            },
+            2 => Self::Spheres {
+                centers: next()?,
+                radii: next()?,
+            },
+            3..=6 => Self::Curves {
+                vertices: next()?,
+                widths: next()?,
+                segment_indices: next()?,
+                kind: CurveKind::ALL[kind as usize - 3],
+            },
+            _ => return None,
+        })
+    }
+}
+/// Instance flags, with the bits OptiX uses for them.
+pub const INSTANCE_DISABLE_CULLING: u32 = 1;
+pub const INSTANCE_FLIP_WINDING: u32 = 2;
+pub const INSTANCE_FORCE_OPAQUE: u32 = 4;
+/// Names of the instance flags accepted by `add_instance`.
+pub const INSTANCE_FLAGS: [(&str, u32); 3] = [
+    ("disable_culling", INSTANCE_DISABLE_CULLING),
+    ("flip_winding", INSTANCE_FLIP_WINDING),
+    ("force_opaque", INSTANCE_FORCE_OPAQUE),
+];
+
+/// Combines the bits of the instance flags named in `flags`.
+pub fn instance_flags(flags: Vec<&str>) -> PyResult<u32> {
+    flags.into_iter().try_fold(0, |bits, flag| {
+        let Some((_, bit)) = INSTANCE_FLAGS.iter().find(|(name, _)| *name == flag) else {
+            return Err(PyErr::new::<PyValueError, _>(format!(
+                "Unknown instance flag {flag:?}, expected one of {:?}!",
+                INSTANCE_FLAGS.map(|(name, _)| name)
+            )));
+        };
+        Ok(bits | bit)
+    })
+}
+
+#[derive(Clone)]
+pub struct InstanceDesc {
     pub geometry: usize,
     pub transform: [f32; 12],
     pub hit_group: u32,
+    /// Rays whose visibility mask shares no bit with this one skip the instance.
+    pub visibility_mask: u8,
+    /// Reported for hits of the instance.
+    pub instance_id: u32,
+    /// Bits of `INSTANCE_FLAGS`.
+    pub flags: u32,
+    /// Added to the index of the hit group when selecting the SBT record.
+    pub sbt_offset: u32,
 }
 
+#[derive(Clone)]
 pub struct ModuleDesc {
     pub asm: String,
     pub entry_point: String,
 }
 
+/// How a hit group intersects non-triangle primitives.
+#[derive(Clone)]
+pub enum Intersection {
+    /// A user program, needed for AABBs.
+    Module(ModuleDesc),
+    /// The built-in module for spheres.
+    Spheres,
+    /// The built-in module for curves of this kind.
+    Curves(CurveKind),
+}
+
+impl Intersection {
+    /// Whether instances of `geometry` can use this intersection.
+    fn supports(intersection: Option<&Self>, geometry: &GeometryDesc) -> bool {
+        match (geometry, intersection) {
+            (GeometryDesc::Triangles { .. }, _) => true,
+            (GeometryDesc::Aabbs { .. }, Some(Self::Module(_))) => true,
+            (GeometryDesc::Spheres { .. }, Some(Self::Spheres)) => true,
+            (GeometryDesc::Curves { kind, .. }, Some(Self::Curves(k))) => kind == k,
+            _ => false,
+        }
+    }
+    fn name(intersection: Option<&Self>) -> String {
+        match intersection {
+            None => "no intersection program".into(),
+            Some(Self::Module(_)) => "a custom intersection program".into(),
+            Some(Self::Spheres) => "the built-in sphere intersection".into(),
+            Some(Self::Curves(kind)) => {
+                format!("the built-in {} curve intersection", kind.name())
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct HitGroupDesc {
     pub closest_hit: ModuleDesc,
     pub any_hit: Option<ModuleDesc>,
-    pub intersection: Option<ModuleDesc>,
+    pub intersection: Option<Intersection>,
 }
+#[derive(Clone)]
 pub struct MissGroupDesc {
     pub miss: ModuleDesc,
 }
 
 #[pyclass]
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct AccelDesc {
     hit_groups: Vec<HitGroupDesc>,
     miss_groups: Vec<MissGroupDesc>,
-    geometries: Vec<GeometryDesc>,
-    instances: Vec<InstanceDesc>,
+    pub geometries: Vec<GeometryDesc>,
+    pub instances: Vec<InstanceDesc>,
 }
 
 #[pymethods]
@@ -75,13 +346,83 @@ impl AccelDesc {
         });
         Ok(id)
     }
-    pub fn add_instance(&mut self, geometry: usize, transform: [f32; 12], hit_group: u32) {
+    /// Adds custom primitives, one per bounding box. `bbox_min` and `bbox_max` hold
+    /// the corners as consecutive xyz triples. Instances of them need a hit group
+    /// with an intersection program.
+    pub fn add_aabbs(&mut self, bbox_min: &PyAny, bbox_max: &PyAny) -> PyResult<usize> {
+        let bbox_min = f32(bbox_min, None)?;
+        let bbox_max = f32(bbox_max, None)?;
+        let id = self.geometries.len();
+        self.geometries
+            .push(GeometryDesc::Aabbs { bbox_min, bbox_max });
+        Ok(id)
+    }
+    /// Adds spheres with centers given as consecutive xyz triples and one radius
+    /// each. Instances of them need a hit group with
+    /// `intersection_builtin="spheres"`.
+    pub fn add_spheres(&mut self, centers: &PyAny, radii: &PyAny) -> PyResult<usize> {
+        let centers = f32(centers, None)?;
+        let radii = f32(radii, None)?;
+        let id = self.geometries.len();
+        self.geometries
+            .push(GeometryDesc::Spheres { centers, radii });
+        Ok(id)
+    }
+    /// Adds curves through the control points `vertices`, given as xyz triples with
+    /// one width each. Each entry of `segment_indices` is the first control point of
+    /// a segment, which spans 2 points for `kind="linear"`, 3 for `"quadratic"` and 4
+    /// for `"cubic"` and `"catmull_rom"`. Instances of them need a hit group whose
+    /// `intersection_builtin` is the same kind.
+    pub fn add_curves(
+        &mut self,
+        vertices: &PyAny,
+        widths: &PyAny,
+        segment_indices: &PyAny,
+        kind: &str,
+    ) -> PyResult<usize> {
+        let kind = CurveKind::from_name(kind)?;
+        let vertices = f32(vertices, None)?;
+        let widths = f32(widths, None)?;
+        let segment_indices = u32(segment_indices, None)?;
+        let id = self.geometries.len();
+        self.geometries.push(GeometryDesc::Curves {
+            vertices,
+            widths,
+            segment_indices,
+            kind,
+        });
+        Ok(id)
+    }
+    /// Adds an instance of `geometry` and returns its index. Rays only see it if
+    /// their visibility mask shares a bit with `visibility_mask`. `instance_id` is
+    /// reported for hits and defaults to the index. `flags` can contain
+    /// `"disable_culling"`, `"flip_winding"` and `"force_opaque"`.
+    #[pyo3(signature = (geometry, transform, hit_group, visibility_mask=0xff, instance_id=None, flags=vec![], sbt_offset=0))]
+    pub fn add_instance(
+        &mut self,
+        geometry: usize,
+        transform: [f32; 12],
+        hit_group: u32,
+        visibility_mask: u8,
+        instance_id: Option<u32>,
+        flags: Vec<&str>,
+        sbt_offset: u32,
+    ) -> PyResult<usize> {
+        let flags = instance_flags(flags)?;
+        let idx = self.instances.len();
         self.instances.push(InstanceDesc {
             geometry,
             transform,
             hit_group,
-        })
+            visibility_mask,
+            instance_id: instance_id.unwrap_or(idx as u32),
+            flags,
+            sbt_offset,
+        });
+        Ok(idx)
     }
+    /// Adds a hit group. Spheres and curves are intersected by built-in modules,
+    /// selected by `intersection_builtin`: `"spheres"` or the kind of the curves.
     pub fn add_hit_group(
         &mut self,
         closest_hit_entry_point: &str,
@@ -90,15 +431,32 @@ impl AccelDesc {
         any_hit_asm: Option<&str>,
         intersection_entry_point: Option<&str>,
         intersection_asm: Option<&str>,
-    ) -> u32 {
-        let any_hit = any_hit_asm.map(|ah| ModuleDesc {
-            asm: ah.into(),
-            entry_point: any_hit_entry_point.unwrap().into(),
-        });
-        let intersection = intersection_asm.map(|int| ModuleDesc {
-            asm: int.into(),
-            entry_point: intersection_entry_point.unwrap().into(),
-        });
+        intersection_builtin: Option<&str>,
+    ) -> PyResult<u32> {
+        let module =
+            |name: &str, asm: Option<&str>, entry_point: Option<&str>| match (asm, entry_point) {
+                (Some(asm), Some(entry_point)) => Ok(Some(ModuleDesc {
+                    asm: asm.into(),
+                    entry_point: entry_point.into(),
+                })),
+                (None, None) => Ok(None),
+                _ => Err(PyErr::new::<PyValueError, _>(format!(
+                    "The {name} program needs both its assembly and its entry point!"
+                ))),
+            };
+        let any_hit = module("any hit", any_hit_asm, any_hit_entry_point)?;
+        let intersection = module("intersection", intersection_asm, intersection_entry_point)?;
+        let intersection = match (intersection, intersection_builtin) {
+            (Some(_), Some(_)) => {
+                return Err(PyErr::new::<PyValueError, _>(
+                    "A hit group can not have both a custom and a built-in intersection!",
+                ))
+            }
+            (Some(module), None) => Some(Intersection::Module(module)),
+            (None, Some("spheres")) => Some(Intersection::Spheres),
+            (None, Some(kind)) => Some(Intersection::Curves(CurveKind::from_name(kind)?)),
+            (None, None) => None,
+        };
 
         let idx = self.hit_groups.len();
         self.hit_groups.push(HitGroupDesc {
@@ -109,7 +467,7 @@ impl AccelDesc {
             any_hit,
             intersection,
         });
-        idx as _
+        Ok(idx as _)
     }
     pub fn add_miss_group(&mut self, entry_point: &str, asm: &str) -> u32 {
         let idx = self.miss_groups.len();
@@ -121,77 +479,268 @@ impl AccelDesc {
         });
         idx as _
     }
+    /// Checks that geometries consist of whole primitives with indices in range and
+    /// that instances refer to existing geometries and to hit groups able to
+    /// intersect them. Raises a ValueError naming the offending geometry or instance.
+    pub fn validate(&self, py: Python) -> PyResult<()> {
+        for i in 0..self.geometries.len() {
+            self.validate_geometry(py, i)?;
+        }
+        for (i, instance) in self.instances.iter().enumerate() {
+            self.validate_instance(i, instance)?;
+        }
+        Ok(())
+    }
 }
 
+impl AccelDesc {
+    /// Checks that geometry `i` consists of whole primitives with indices in range.
+    pub fn validate_geometry(&self, py: Python, i: usize) -> PyResult<()> {
+        let invalid = |msg: String| Err(PyErr::new::<PyValueError, _>(msg));
+        match &self.geometries[i] {
+            GeometryDesc::Triangles { vertices, indices } => {
+                if vertices.0.size() % 3 != 0 {
+                    return invalid(format!(
+                        "Geometry {i}: {} vertex coordinates are not a multiple of 3!",
+                        vertices.0.size()
+                    ));
+                }
+                if indices.0.size() % 3 != 0 {
+                    return invalid(format!(
+                        "Geometry {i}: {} indices are not a multiple of 3!",
+                        indices.0.size()
+                    ));
+                }
+                let n_vertices = vertices.0.size() / 3;
+                indices.eval(py)?;
+                if let HostData::U32(indices) = indices.read(py)? {
+                    if let Some(idx) = indices.iter().find(|&&idx| idx as usize >= n_vertices) {
+                        return invalid(format!(
+                            "Geometry {i}: index {idx} is out of range for {n_vertices} vertices!"
+                        ));
+                    }
+                }
+            }
+            GeometryDesc::Aabbs { bbox_min, bbox_max } => {
+                if bbox_min.0.size() % 3 != 0 || bbox_min.0.size() != bbox_max.0.size() {
+                    return invalid(format!(
+                        "Geometry {i}: bbox_min and bbox_max need the same number of xyz \
+                         triples, got {} and {} values!",
+                        bbox_min.0.size(),
+                        bbox_max.0.size()
+                    ));
+                }
+                bbox_min.eval(py)?;
+                bbox_max.eval(py)?;
+                if let (HostData::F32(min), HostData::F32(max)) =
+                    (bbox_min.read(py)?, bbox_max.read(py)?)
+                {
+                    if let Some(j) = (0..min.len()).find(|&j| min[j] > max[j]) {
+                        return invalid(format!(
+                            "Geometry {i}: box {} has bbox_min {} above bbox_max {} along {}!",
+                            j / 3,
+                            min[j],
+                            max[j],
+                            ["x", "y", "z"][j % 3]
+                        ));
+                    }
+                }
+            }
+            GeometryDesc::Spheres { centers, radii } => {
+                if centers.0.size() % 3 != 0 || centers.0.size() / 3 != radii.0.size() {
+                    return invalid(format!(
+                        "Geometry {i}: spheres need one radius per xyz triple, got {} \
+                         center coordinates and {} radii!",
+                        centers.0.size(),
+                        radii.0.size()
+                    ));
+                }
+            }
+            GeometryDesc::Curves {
+                vertices,
+                widths,
+                segment_indices,
+                kind,
+            } => {
+                if vertices.0.size() % 3 != 0 || vertices.0.size() / 3 != widths.0.size() {
+                    return invalid(format!(
+                        "Geometry {i}: curves need one width per xyz triple, got {} \
+                         vertex coordinates and {} widths!",
+                        vertices.0.size(),
+                        widths.0.size()
+                    ));
+                }
+                let n_vertices = vertices.0.size() / 3;
+                let n = kind.n_control_points();
+                segment_indices.eval(py)?;
+                if let HostData::U32(indices) = segment_indices.read(py)? {
+                    if let Some(idx) = indices.iter().find(|&&idx| idx as usize + n > n_vertices) {
+                        return invalid(format!(
+                            "Geometry {i}: segment {idx} needs {n} control points, but \
+                             there are {n_vertices} vertices!"
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Checks that `instance`, the `i`th one, refers to an existing geometry and to a
+    /// hit group able to intersect it.
+    pub fn validate_instance(&self, i: usize, instance: &InstanceDesc) -> PyResult<()> {
+        let invalid = |msg: String| Err(PyErr::new::<PyValueError, _>(msg));
+        if instance.geometry >= self.geometries.len() {
+            return invalid(format!(
+                "Instance {i}: geometry {} does not exist, there are {} geometries!",
+                instance.geometry,
+                self.geometries.len()
+            ));
+        }
+        if instance.hit_group as usize >= self.hit_groups.len() {
+            return invalid(format!(
+                "Instance {i}: hit group {} does not exist, there are {} hit groups!",
+                instance.hit_group,
+                self.hit_groups.len()
+            ));
+        }
+        let geometry = &self.geometries[instance.geometry];
+        let intersection = self.hit_groups[instance.hit_group as usize]
+            .intersection
+            .as_ref();
+        if !Intersection::supports(intersection, geometry) {
+            return invalid(format!(
+                "Instance {i}: geometry {} consists of {}, but hit group {} has {}!",
+                instance.geometry,
+                geometry.name(),
+                instance.hit_group,
+                Intersection::name(intersection)
+            ));
+        }
+        Ok(())
+    }
+    /// Calls `build` with the rjit view of this description.
+    pub fn with_rjit<T>(&self, build: impl FnOnce(rjit::AccelDesc) -> T) -> T {
+        let geometries = self
+            .geometries
+            .iter()
+            .map(|g| match g {
+                GeometryDesc::Triangles { vertices, indices } => rjit::GeometryDesc::Triangles {
+                    vertices: &vertices.0,
+                    indices: &indices.0,
+                },
+                GeometryDesc::Aabbs { bbox_min, bbox_max } => rjit::GeometryDesc::Aabbs {
+                    bbox_min: &bbox_min.0,
+                    bbox_max: &bbox_max.0,
+                },
+                GeometryDesc::Spheres { centers, radii } => rjit::GeometryDesc::Spheres {
+                    centers: &centers.0,
+                    radii: &radii.0,
+                },
+                GeometryDesc::Curves {
+                    vertices,
+                    widths,
+                    segment_indices,
+                    kind,
+                } => rjit::GeometryDesc::Curves {
+                    vertices: &vertices.0,
+                    widths: &widths.0,
+                    segment_indices: &segment_indices.0,
+                    kind: (*kind).into(),
+                },
+            })
+            .collect::<Vec<_>>();
+        let instances = self
+            .instances
+            .iter()
+            .map(|i| rjit::InstanceDesc {
+                geometry: i.geometry,
+                transform: i.transform,
+                hit_group: i.hit_group,
+                visibility_mask: i.visibility_mask,
+                instance_id: i.instance_id,
+                flags: i.flags,
+                sbt_offset: i.sbt_offset,
+            })
+            .collect::<Vec<_>>();
+
+        let hit_groups = self
+            .hit_groups
+            .iter()
+            .map(|hg| rjit::HitGroupDesc {
+                closest_hit: rjit::ModuleDesc {
+                    asm: &hg.closest_hit.asm,
+                    entry_point: &hg.closest_hit.entry_point,
+                },
+                any_hit: hg.any_hit.as_ref().map(|ah| rjit::ModuleDesc {
+                    asm: &ah.asm,
+                    entry_point: &ah.entry_point,
+                }),
+                intersection: hg.intersection.as_ref().map(|int| match int {
+                    Intersection::Module(int) => rjit::IntersectionDesc::Module(rjit::ModuleDesc {
+                        asm: &int.asm,
+                        entry_point: &int.entry_point,
+                    }),
+                    Intersection::Spheres => rjit::IntersectionDesc::Spheres,
+                    Intersection::Curves(kind) => rjit::IntersectionDesc::Curves((*kind).into()),
+                }),
+            })
+            .collect::<Vec<_>>();
+
+        let miss_groups = self
+            .miss_groups
+            .iter()
+            .map(|mg| rjit::MissGroupDesc {
+                miss: rjit::ModuleDesc {
+                    asm: &mg.miss.asm,
+                    entry_point: &mg.miss.entry_point,
+                },
+            })
+            .collect::<Vec<_>>();
+        let sbt = rjit::SBTDesc {
+            hit_groups: &hit_groups,
+            miss_groups: &miss_groups,
+        };
+        let desc = rjit::AccelDesc {
+            sbt,
+            geometries: &geometries,
+            instances: &instances,
+        };
+        build(desc)
+    }
+}
+
+/// Builds an acceleration structure from `desc` in `ctx`, or the current context,
+/// after validating it.
 #[pyfunction]
-pub fn accel(desc: &AccelDesc) -> Result<Var> {
-    let geometries = desc
-        .geometries
-        .iter()
-        .map(|g| match g {
-            GeometryDesc::Triangles { vertices, indices } => rjit::GeometryDesc::Triangles {
-                vertices: &vertices.0,
-                indices: &indices.0,
-            },
-        })
-        .collect::<Vec<_>>();
-    let instances = desc
-        .instances
-        .iter()
-        .map(|i| rjit::InstanceDesc {
-            geometry: i.geometry,
-            transform: i.transform,
-            hit_group: i.hit_group,
-        })
-        .collect::<Vec<_>>();
-
-    let hit_groups = desc
-        .hit_groups
-        .iter()
-        .map(|hg| rjit::HitGroupDesc {
-            closest_hit: rjit::ModuleDesc {
-                asm: &hg.closest_hit.asm,
-                entry_point: &hg.closest_hit.entry_point,
-            },
-            any_hit: hg.any_hit.as_ref().map(|ah| rjit::ModuleDesc {
-                asm: &ah.asm,
-                entry_point: &ah.entry_point,
-            }),
-            intersection: hg.intersection.as_ref().map(|int| rjit::ModuleDesc {
-                asm: &int.asm,
-                entry_point: &int.entry_point,
-            }),
-        })
-        .collect::<Vec<_>>();
-
-    let miss_groups = desc
-        .miss_groups
-        .iter()
-        .map(|mg| rjit::MissGroupDesc {
-            miss: rjit::ModuleDesc {
-                asm: &mg.miss.asm,
-                entry_point: &mg.miss.entry_point,
-            },
-        })
-        .collect::<Vec<_>>();
-    let sbt = rjit::SBTDesc {
-        hit_groups: &hit_groups,
-        miss_groups: &miss_groups,
-    };
-    let desc = rjit::AccelDesc {
-        sbt,
-        geometries: &geometries,
-        instances: &instances,
-    };
-    Ok(Var(IR.accel(desc)?))
+#[pyo3(signature = (desc, ctx = None))]
+pub fn accel(py: Python, desc: &AccelDesc, ctx: Option<Context>) -> PyResult<Var> {
+    context::with_ctx(ctx.as_ref(), || {
+        desc.validate(py)?;
+        if interp::is_enabled(&ir()) {
+            return Ok(bvh::accel(desc)?);
+        }
+        Ok(Var(desc.with_rjit(|desc| ir().accel(desc))?))
+    })
 }
 
 macro_rules! initializer {
     ($ty:ident) => {
         paste::paste! {
+            /// Creates a variable in `ctx`, or the current context.
             #[pyfunction]
-            pub fn $ty(value: &PyAny, num: Option<usize>) -> PyResult<Var> {
+            #[pyo3(signature = (value, num = None, ctx = None))]
+            pub fn $ty(value: &PyAny, num: Option<usize>, ctx: Option<Context>) -> PyResult<Var> {
+                context::with_ctx(ctx.as_ref(), || [<$ty _in_current>](value, num))
+            }
+
+            fn [<$ty _in_current>](value: &PyAny, num: Option<usize>) -> PyResult<Var> {
                 if let Ok(val) = value.extract::<Var>(){
+                    if val.0.trace() != &ir() {
+                        return Err(PyErr::new::<PyValueError, _>(format!(
+                            "Variable {} belongs to another context than the current one!",
+                            val.name()
+                        )));
+                    }
                     if val.0.ty() == rjit::VarType::[<$ty:camel>] {
                         return Ok(val);
                     } else {
@@ -199,13 +748,13 @@ macro_rules! initializer {
                     }
                 }
                 if let Ok(val) = value.extract::<$ty>() {
-                    return Ok(Var(IR.sized_literal::<$ty>(val, num.unwrap_or(1))?));
+                    return Ok(Var(ir().sized_literal::<$ty>(val, num.unwrap_or(1))?));
                 }
                 if let Ok(val) = value.extract::<Vec<$ty>>() {
-                    return Ok(Var(IR.array(&val)?));
+                    return Ok(Var(HostData::[<$ty:camel>](val).to_var()?));
                 }
                 if let Ok(val) = value.extract::<numpy::PyReadonlyArray1<$ty>>() {
-                    return Ok(Var(IR.array(&val.to_vec()?)?));
+                    return Ok(Var(HostData::[<$ty:camel>](val.to_vec()?).to_var()?));
                 }
 
                 Err(PyErr::new::<PyTypeError, _>(
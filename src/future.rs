@@ -0,0 +1,117 @@
+use crate::context::ir;
+use crate::funcs;
+use crate::host::HostData;
+use crate::var::Var;
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// What a background task produces; converted to a python object by `Future::wait`.
+pub enum Output {
+    None,
+    Host(HostData),
+}
+
+struct Inner {
+    handle: Option<JoinHandle<Result<Output>>>,
+    /// Output of the joined task, not yet converted to a python object.
+    finished: Option<Result<Output>>,
+    result: Option<std::result::Result<PyObject, String>>,
+}
+
+/// Handle to work running on a background thread, returned by `eval_async` and
+/// `Var.to_numpy_async`. Can be awaited in asyncio code.
+#[pyclass]
+pub struct Future {
+    inner: Mutex<Inner>,
+    /// Set by the worker when it is done, so that `done` does not need `inner`,
+    /// which `wait` holds while joining.
+    done: Arc<AtomicBool>,
+}
+
+impl Future {
+    pub fn spawn(f: impl FnOnce() -> Result<Output> + Send + 'static) -> Self {
+        let done = Arc::new(AtomicBool::new(false));
+        let handle = std::thread::spawn({
+            let done = done.clone();
+            move || {
+                let res = f();
+                done.store(true, Ordering::Release);
+                res
+            }
+        });
+        Self {
+            inner: Mutex::new(Inner {
+                handle: Some(handle),
+                finished: None,
+                result: None,
+            }),
+            done,
+        }
+    }
+}
+
+#[pymethods]
+impl Future {
+    /// Returns `True` if the work has completed. Never blocks.
+    pub fn done(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+    /// Blocks until the work has completed and returns its result, `None` for
+    /// `eval_async` and a numpy array for `to_numpy_async`.
+    pub fn wait(&self, py: Python) -> PyResult<PyObject> {
+        // Join without holding the GIL, so the worker and other python threads can
+        // make progress.
+        py.allow_threads(|| {
+            let mut inner = self.inner.lock();
+            if let Some(handle) = inner.handle.take() {
+                let res = handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("Background evaluation panicked!")));
+                inner.finished = Some(res);
+            }
+        });
+        let mut inner = self.inner.lock();
+        if let Some(res) = inner.finished.take() {
+            inner.result = Some(
+                res.map(|out| match out {
+                    Output::None => py.None(),
+                    Output::Host(data) => data.into_numpy(py),
+                })
+                .map_err(|err| err.to_string()),
+            );
+        }
+        match inner.result.as_ref().unwrap() {
+            Ok(obj) => Ok(obj.clone_ref(py)),
+            Err(err) => Err(PyErr::new::<PyRuntimeError, _>(err.clone())),
+        }
+    }
+    /// Waits for the result in the default executor of the running event loop.
+    pub fn __await__(slf: Py<Self>, py: Python) -> PyResult<PyObject> {
+        let wait = slf.getattr(py, "wait")?;
+        let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+        let fut = event_loop.call_method1("run_in_executor", (py.None(), wait))?;
+        Ok(fut.call_method0("__await__")?.into_py(py))
+    }
+}
+
+/// Starts evaluating `vars` on a background thread, or all scheduled variables if
+/// `vars` is empty.
+#[pyfunction]
+#[pyo3(signature = (*vars))]
+pub fn eval_async(vars: &PyTuple) -> PyResult<Future> {
+    let vars = vars
+        .iter()
+        .map(|v| Ok(v.extract::<Var>()?.0.clone()))
+        .collect::<PyResult<Vec<_>>>()?;
+    let ir = vars.first().map(|v| v.trace().clone()).unwrap_or_else(ir);
+    Ok(Future::spawn(move || {
+        funcs::eval_on(&ir, &vars)?;
+        Ok(Output::None)
+    }))
+}
@@ -0,0 +1,154 @@
+use crate::context::ir;
+use crate::interp;
+use anyhow::{anyhow, Result};
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+use rjit::{VarRef, VarType};
+use std::fmt;
+
+/// Contents of a variable, copied to the host.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HostData {
+    Bool(Vec<bool>),
+    I8(Vec<i8>),
+    U8(Vec<u8>),
+    I16(Vec<i16>),
+    U16(Vec<u16>),
+    I32(Vec<i32>),
+    U32(Vec<u32>),
+    I64(Vec<i64>),
+    U64(Vec<u64>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+
+/// Expands `$f` once for every variant of `HostData`, with `$v` bound to its values.
+macro_rules! for_each_variant {
+    ($data:expr, $v:ident => $f:expr) => {
+        match $data {
+            HostData::Bool($v) => $f,
+            HostData::I8($v) => $f,
+            HostData::U8($v) => $f,
+            HostData::I16($v) => $f,
+            HostData::U16($v) => $f,
+            HostData::I32($v) => $f,
+            HostData::U32($v) => $f,
+            HostData::I64($v) => $f,
+            HostData::U64($v) => $f,
+            HostData::F32($v) => $f,
+            HostData::F64($v) => $f,
+        }
+    };
+}
+
+impl HostData {
+    /// Copies `var` to the host, evaluating it with the cpu interpreter if its
+    /// context uses the cpu backend.
+    pub fn read(var: &VarRef) -> Result<Self> {
+        if let Some(data) = interp::read(var) {
+            return data;
+        }
+        Self::read_device(var)
+    }
+    /// Copies `var` from the device memory of its backend.
+    pub fn read_device(var: &VarRef) -> Result<Self> {
+        Ok(match var.ty() {
+            VarType::Bool => Self::Bool(var.to_host()?),
+            VarType::I8 => Self::I8(var.to_host()?),
+            VarType::U8 => Self::U8(var.to_host()?),
+            VarType::I16 => Self::I16(var.to_host()?),
+            VarType::U16 => Self::U16(var.to_host()?),
+            VarType::I32 => Self::I32(var.to_host()?),
+            VarType::U32 => Self::U32(var.to_host()?),
+            VarType::I64 => Self::I64(var.to_host()?),
+            VarType::U64 => Self::U64(var.to_host()?),
+            VarType::F32 => Self::F32(var.to_host()?),
+            VarType::F64 => Self::F64(var.to_host()?),
+            ty => {
+                return Err(anyhow!(
+                    "Can not copy variables of type {ty:?} to the host!"
+                ))
+            }
+        })
+    }
+    pub fn ty(&self) -> VarType {
+        match self {
+            Self::Bool(_) => VarType::Bool,
+            Self::I8(_) => VarType::I8,
+            Self::U8(_) => VarType::U8,
+            Self::I16(_) => VarType::I16,
+            Self::U16(_) => VarType::U16,
+            Self::I32(_) => VarType::I32,
+            Self::U32(_) => VarType::U32,
+            Self::I64(_) => VarType::I64,
+            Self::U64(_) => VarType::U64,
+            Self::F32(_) => VarType::F32,
+            Self::F64(_) => VarType::F64,
+        }
+    }
+    pub fn size(&self) -> usize {
+        for_each_variant!(self, v => v.len())
+    }
+    /// The values reinterpreted as bits, zero extended to 64 bits.
+    pub fn bits(&self) -> Vec<u64> {
+        match self {
+            Self::Bool(v) => v.iter().map(|x| *x as u64).collect(),
+            Self::I8(v) => v.iter().map(|x| *x as u8 as u64).collect(),
+            Self::U8(v) => v.iter().map(|x| *x as u64).collect(),
+            Self::I16(v) => v.iter().map(|x| *x as u16 as u64).collect(),
+            Self::U16(v) => v.iter().map(|x| *x as u64).collect(),
+            Self::I32(v) => v.iter().map(|x| *x as u32 as u64).collect(),
+            Self::U32(v) => v.iter().map(|x| *x as u64).collect(),
+            Self::I64(v) => v.iter().map(|x| *x as u64).collect(),
+            Self::U64(v) => v.clone(),
+            Self::F32(v) => v.iter().map(|x| x.to_bits() as u64).collect(),
+            Self::F64(v) => v.iter().map(|x| x.to_bits()).collect(),
+        }
+    }
+    /// Inverse of `bits`.
+    pub fn from_bits(ty: &VarType, bits: impl Iterator<Item = u64>) -> Result<Self> {
+        Ok(match ty {
+            VarType::Bool => Self::Bool(bits.map(|x| x != 0).collect()),
+            VarType::I8 => Self::I8(bits.map(|x| x as i8).collect()),
+            VarType::U8 => Self::U8(bits.map(|x| x as u8).collect()),
+            VarType::I16 => Self::I16(bits.map(|x| x as i16).collect()),
+            VarType::U16 => Self::U16(bits.map(|x| x as u16).collect()),
+            VarType::I32 => Self::I32(bits.map(|x| x as i32).collect()),
+            VarType::U32 => Self::U32(bits.map(|x| x as u32).collect()),
+            VarType::I64 => Self::I64(bits.map(|x| x as i64).collect()),
+            VarType::U64 => Self::U64(bits.collect()),
+            VarType::F32 => Self::F32(bits.map(|x| f32::from_bits(x as u32)).collect()),
+            VarType::F64 => Self::F64(bits.map(f64::from_bits).collect()),
+            ty => {
+                return Err(anyhow!(
+                    "Variables of type {ty:?} can not be held on the host!"
+                ))
+            }
+        })
+    }
+    /// Uploads the values to a new variable in the current context. Contexts using
+    /// the cpu backend keep them on the host.
+    pub fn to_var(&self) -> Result<VarRef> {
+        if let Some(var) = interp::upload(&ir(), self.clone()) {
+            return var;
+        }
+        Ok(for_each_variant!(self, v => ir().array(v)?))
+    }
+    /// Creates a literal of size `size` from the first value in the current context.
+    pub fn to_literal(&self, size: usize) -> Result<VarRef> {
+        Ok(for_each_variant!(self, v => ir().sized_literal(v[0], size)?))
+    }
+    pub fn into_numpy(self, py: Python) -> PyObject {
+        for_each_variant!(self, v => numpy::PyArray1::from_vec(py, v).into_py(py))
+    }
+    pub fn into_list(self, py: Python) -> &PyList {
+        for_each_variant!(self, v => PyList::new(py, v))
+    }
+}
+
+impl fmt::Display for HostData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ty = format!("{:?}", self.ty()).to_lowercase();
+        for_each_variant!(self, v => write!(f, "{ty}{:?}", v.as_slice()))
+    }
+}
@@ -0,0 +1,558 @@
+//! Reference interpreter used by the "cpu" backend.
+//! Instead of compiling kernels, it walks the recorded trace and evaluates every
+//! operation on the host. It is slow, but needs no GPU and serves as a correctness
+//! oracle for the other backends.
+use crate::host::HostData;
+use anyhow::{anyhow, bail, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rjit::{Op, ReduceOp, Trace, VarRef, VarType};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Traces using the cpu backend. The lock is only held to look up an interpreter,
+/// each interpreter locks its values while reading or writing them, so traces can
+/// be interpreted concurrently.
+static INTERPRETERS: Lazy<Mutex<Vec<Arc<Interpreter>>>> = Lazy::new(|| Mutex::new(vec![]));
+
+struct Interpreter {
+    trace: Trace,
+    /// Values of uploaded and evaluated variables by id. The variables are held,
+    /// so that rjit does not reuse their ids, until `release` finds that nothing
+    /// else refers to them.
+    values: Mutex<HashMap<usize, (VarRef, HostData)>>,
+    /// Ids of the placeholders created by `upload`.
+    uploads: Mutex<HashSet<usize>>,
+    /// Held while taking variables out of the schedule of the trace and running
+    /// them, so that concurrent evals and reads neither lose nor repeat a scatter.
+    schedule: Mutex<()>,
+}
+
+fn find(trace: &Trace) -> Option<Arc<Interpreter>> {
+    INTERPRETERS
+        .lock()
+        .iter()
+        .find(|i| &i.trace == trace)
+        .cloned()
+}
+
+/// Makes `trace` evaluate on the host.
+/// No rjit backend is set for it: variables are uploaded with `upload` and never
+/// allocated on a device.
+pub fn enable(trace: &Trace) {
+    let mut interpreters = INTERPRETERS.lock();
+    if !interpreters.iter().any(|i| &i.trace == trace) {
+        interpreters.push(Arc::new(Interpreter {
+            trace: trace.clone(),
+            values: Mutex::new(HashMap::new()),
+            uploads: Mutex::new(HashSet::new()),
+            schedule: Mutex::new(()),
+        }));
+    }
+}
+
+pub fn disable(trace: &Trace) {
+    INTERPRETERS.lock().retain(|i| &i.trace != trace);
+}
+
+pub fn is_enabled(trace: &Trace) -> bool {
+    find(trace).is_some()
+}
+
+/// Whether the interpreter of the trace of `var` holds its value, i.e. it was
+/// uploaded or evaluated on the host.
+pub fn holds(var: &VarRef) -> bool {
+    find(var.trace()).map_or(false, |i| i.values.lock().contains_key(&var.id()))
+}
+
+/// Whether `var` was created by `upload`, the host counterpart of `Op::Data`.
+pub fn is_upload(var: &VarRef) -> bool {
+    find(var.trace()).map_or(false, |i| i.uploads.lock().contains(&var.id()))
+}
+
+/// Drops the values of the trace of `var` that only the interpreter still refers
+/// to, counting `var` as released. Called whenever a Var is dropped. Releasing a value can release the values it was computed from, so
+/// this repeats until nothing changes, costing O(#held values) per pass.
+pub fn release(var: &VarRef) {
+    let Some(interpreter) = find(var.trace()) else {
+        return;
+    };
+    let mut released = vec![];
+    loop {
+        let mut values = interpreter.values.lock();
+        let n_values = values.len();
+        values.retain(|&id, (held, _)| {
+            let refs = if id == var.id() { 2 } else { 1 };
+            let release = held.ref_count() <= refs;
+            if release {
+                released.push(id);
+            }
+            !release
+        });
+        if values.len() == n_values {
+            break;
+        }
+    }
+    let mut uploads = interpreter.uploads.lock();
+    for id in released {
+        uploads.remove(&id);
+    }
+}
+
+/// Creates a variable holding `data` if `trace` uses the cpu backend. Returns `None`
+/// otherwise. The variable is a placeholder in the trace, its value only lives in
+/// the interpreter.
+pub fn upload(trace: &Trace, data: HostData) -> Option<Result<VarRef>> {
+    let interpreter = find(trace)?;
+    Some((|| {
+        let var = trace.index(data.size()).cast(&data.ty())?;
+        interpreter.uploads.lock().insert(var.id());
+        interpreter
+            .values
+            .lock()
+            .insert(var.id(), (var.clone(), data));
+        Ok(var)
+    })())
+}
+
+/// Takes the pending scatters out of the scheduled variables of `trace`, leaving
+/// the others scheduled.
+fn side_effects(trace: &Trace) -> Vec<VarRef> {
+    let (effects, rest): (Vec<_>, Vec<_>) = trace
+        .take_scheduled()
+        .into_iter()
+        .partition(|v| matches!(v.op(), Op::Scatter | Op::ScatterReduce(_)));
+    for var in rest {
+        var.schedule();
+    }
+    effects
+}
+
+/// Evaluates `vars`, or all scheduled variables if `vars` is empty, if `trace` uses
+/// the cpu backend. Returns `None` otherwise. Pending scatters run first, so that
+/// their targets are up to date.
+pub fn eval(trace: &Trace, vars: &[VarRef]) -> Option<Result<()>> {
+    let interpreter = find(trace)?;
+    let _guard = interpreter.schedule.lock();
+    let vars = if vars.is_empty() {
+        trace.take_scheduled()
+    } else {
+        side_effects(trace)
+            .into_iter()
+            .chain(vars.iter().cloned())
+            .collect()
+    };
+    Some(vars.iter().try_for_each(|var| {
+        let data = interpreter.eval(var)?;
+        interpreter
+            .values
+            .lock()
+            .insert(var.id(), (var.clone(), data));
+        Ok(())
+    }))
+}
+
+/// Reads `var` if its trace uses the cpu backend, after running pending scatters.
+/// Returns `None` otherwise.
+pub fn read(var: &VarRef) -> Option<Result<HostData>> {
+    let interpreter = find(var.trace())?;
+    let _guard = interpreter.schedule.lock();
+    Some((|| {
+        for effect in side_effects(var.trace()) {
+            interpreter.eval(&effect)?;
+        }
+        interpreter.eval(var)
+    })())
+}
+
+/// Value of lane `i` of `v`, broadcasting variables of size 1.
+fn at<T: Copy>(v: &[T], i: usize) -> T {
+    v[if v.len() == 1 { 0 } else { i }]
+}
+
+/// Applies `f` lane by lane to two operands, broadcasting operands of size 1.
+fn map2<T: Copy, U>(n: usize, a: &[T], b: &[T], f: impl Fn(T, T) -> U) -> Vec<U> {
+    (0..n).map(|i| f(at(a, i), at(b, i))).collect()
+}
+
+fn map1<T: Copy, U>(n: usize, a: &[T], f: impl Fn(T) -> U) -> Vec<U> {
+    (0..n).map(|i| f(at(a, i))).collect()
+}
+
+/// Applies a lane-wise operation to two operands of the same type. Each group of
+/// variants has its own function, e.g. wrapping arithmetic for integers. Evaluates
+/// to `None` for types not listed.
+macro_rules! binary {
+    ($n:expr, $a:expr, $b:expr, $([$($v:ident),*] => $f:expr),+) => {
+        match ($a, $b) {
+            $($((HostData::$v(a), HostData::$v(b)) => {
+                Some(HostData::$v(map2($n, a, b, $f)))
+            })*)+
+            _ => None,
+        }
+    };
+}
+
+/// Like `binary!`, for comparisons of any type returning bools.
+macro_rules! compare {
+    ($n:expr, $a:expr, $b:expr, $f:expr) => {
+        compare!($n, $a, $b, $f, [Bool, I8, U8, I16, U16, I32, U32, I64, U64, F32, F64])
+    };
+    ($n:expr, $a:expr, $b:expr, $f:expr, [$($v:ident),*]) => {
+        match ($a, $b) {
+            $((HostData::$v(a), HostData::$v(b)) => Some(HostData::Bool(map2($n, a, b, $f))),)*
+            _ => None,
+        }
+    };
+}
+
+/// Like `binary!`, for a single operand.
+macro_rules! unary {
+    ($n:expr, $a:expr, $([$($v:ident),*] => $f:expr),+) => {
+        match $a {
+            $($(HostData::$v(a) => Some(HostData::$v(map1($n, a, $f))),)*)+
+            _ => None,
+        }
+    };
+}
+
+/// Converts every lane of `$a` to `$t` with an `as` cast.
+macro_rules! cast_to {
+    ($n:expr, $a:expr, $t:ty) => {
+        match $a {
+            HostData::Bool(a) => (0..$n).map(|i| at(a, i) as u8 as $t).collect(),
+            HostData::I8(a) => (0..$n).map(|i| at(a, i) as $t).collect(),
+            HostData::U8(a) => (0..$n).map(|i| at(a, i) as $t).collect(),
+            HostData::I16(a) => (0..$n).map(|i| at(a, i) as $t).collect(),
+            HostData::U16(a) => (0..$n).map(|i| at(a, i) as $t).collect(),
+            HostData::I32(a) => (0..$n).map(|i| at(a, i) as $t).collect(),
+            HostData::U32(a) => (0..$n).map(|i| at(a, i) as $t).collect(),
+            HostData::I64(a) => (0..$n).map(|i| at(a, i) as $t).collect(),
+            HostData::U64(a) => (0..$n).map(|i| at(a, i) as $t).collect(),
+            HostData::F32(a) => (0..$n).map(|i| at(a, i) as $t).collect(),
+            HostData::F64(a) => (0..$n).map(|i| at(a, i) as $t).collect(),
+        }
+    };
+}
+
+fn cast(n: usize, a: &HostData, ty: &VarType) -> Result<HostData> {
+    Ok(match ty {
+        VarType::Bool => {
+            let a: Vec<f64> = cast_to!(n, a, f64);
+            HostData::Bool(a.into_iter().map(|x| x != 0.).collect())
+        }
+        VarType::I8 => HostData::I8(cast_to!(n, a, i8)),
+        VarType::U8 => HostData::U8(cast_to!(n, a, u8)),
+        VarType::I16 => HostData::I16(cast_to!(n, a, i16)),
+        VarType::U16 => HostData::U16(cast_to!(n, a, u16)),
+        VarType::I32 => HostData::I32(cast_to!(n, a, i32)),
+        VarType::U32 => HostData::U32(cast_to!(n, a, u32)),
+        VarType::I64 => HostData::I64(cast_to!(n, a, i64)),
+        VarType::U64 => HostData::U64(cast_to!(n, a, u64)),
+        VarType::F32 => HostData::F32(cast_to!(n, a, f32)),
+        VarType::F64 => HostData::F64(cast_to!(n, a, f64)),
+        ty => bail!("Can not cast to {ty:?} on the cpu backend!"),
+    })
+}
+
+fn mask(data: Option<&HostData>, n: usize) -> Result<Vec<bool>> {
+    match data {
+        None => Ok(vec![true; n]),
+        Some(HostData::Bool(m)) => Ok((0..n).map(|i| at(m, i)).collect()),
+        Some(data) => bail!("Expected a boolean mask, got {:?}!", data.ty()),
+    }
+}
+
+fn indices(data: &HostData, n: usize) -> Result<Vec<usize>> {
+    match data {
+        HostData::U32(idx) => Ok((0..n).map(|i| at(idx, i) as usize).collect()),
+        data => bail!("Expected u32 indices, got {:?}!", data.ty()),
+    }
+}
+
+/// Reads `src[idx]` for every active lane, zero for inactive lanes.
+fn gather(src: &HostData, idx: &[usize], mask: &[bool]) -> Result<HostData> {
+    let bits = src.bits();
+    let lanes = idx
+        .iter()
+        .zip(mask)
+        .map(|(&i, &active)| match active {
+            false => Ok(0),
+            true => bits.get(i).copied().ok_or_else(|| {
+                anyhow!(
+                    "Gather index {i} is out of bounds for a variable of size {}!",
+                    bits.len()
+                )
+            }),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    HostData::from_bits(&src.ty(), lanes.into_iter())
+}
+
+/// Writes, or with `op` accumulates, `src` into `dst[idx]` for every active lane.
+fn scatter(
+    src: &HostData,
+    dst: &mut HostData,
+    idx: &[usize],
+    mask: &[bool],
+    op: Option<&ReduceOp>,
+) -> Result<()> {
+    let size = dst.size();
+    for (lane, (&i, &active)) in idx.iter().zip(mask).enumerate() {
+        if !active {
+            continue;
+        }
+        if i >= size {
+            bail!("Scatter index {i} is out of bounds for a variable of size {size}!");
+        }
+        macro_rules! write_lane {
+            ($($v:ident),*) => {
+                match (&mut *dst, src) {
+                    $((HostData::$v(dst), HostData::$v(src)) => match op {
+                        None => dst[i] = at(src, lane),
+                        Some(ReduceOp::Add) => dst[i] = add(dst[i], at(src, lane)),
+                        Some(op) => bail!("Reduction {op:?} is not supported on the cpu backend!"),
+                    },)*
+                    (dst, src) => bail!("Can not scatter {:?} into {:?}!", src.ty(), dst.ty()),
+                }
+            };
+        }
+        write_lane!(Bool, I8, U8, I16, U16, I32, U32, I64, U64, F32, F64);
+    }
+    Ok(())
+}
+
+/// Addition used by scatter reductions: wrapping for integers, `or` for bools.
+fn add<T: Add>(a: T, b: T) -> T {
+    a.add(b)
+}
+
+trait Add {
+    fn add(self, other: Self) -> Self;
+}
+macro_rules! impl_add {
+    ($($t:ty),*) => {
+        $(impl Add for $t {
+            fn add(self, other: Self) -> Self {
+                self.wrapping_add(other)
+            }
+        })*
+    };
+}
+impl_add!(i8, u8, i16, u16, i32, u32, i64, u64);
+impl Add for bool {
+    fn add(self, other: Self) -> Self {
+        self | other
+    }
+}
+impl Add for f32 {
+    fn add(self, other: Self) -> Self {
+        self + other
+    }
+}
+impl Add for f64 {
+    fn add(self, other: Self) -> Self {
+        self + other
+    }
+}
+
+impl Interpreter {
+    /// Evaluates `var` and everything it depends on. Intermediate values are only
+    /// kept for the duration of the call.
+    fn eval(&self, var: &VarRef) -> Result<HostData> {
+        let mut memo = HashMap::<usize, HostData>::new();
+        // Iterative post-order traversal, as traces can be deeper than the stack.
+        let mut stack = vec![(var.clone(), false)];
+        while let Some((var, ready)) = stack.pop() {
+            if memo.contains_key(&var.id()) {
+                continue;
+            }
+            if let Some((_, data)) = self.values.lock().get(&var.id()) {
+                memo.insert(var.id(), data.clone());
+                continue;
+            }
+            let deps = var.deps();
+            if !ready {
+                stack.push((var.clone(), true));
+                stack.extend(deps.into_iter().map(|d| (d, false)));
+                continue;
+            }
+            let args = deps
+                .iter()
+                .map(|d| memo.get(&d.id()).unwrap())
+                .collect::<Vec<_>>();
+            let data = self.op(&var, &deps, &args)?;
+            memo.insert(var.id(), data);
+            // Later reads of a scatter target have to see the written values.
+            if matches!(var.op(), Op::Scatter | Op::ScatterReduce(_)) {
+                let dst = self.values.lock()[&deps[1].id()].1.clone();
+                memo.insert(deps[1].id(), dst);
+            }
+        }
+        Ok(memo.remove(&var.id()).unwrap())
+    }
+
+    fn op(&self, var: &VarRef, deps: &[VarRef], args: &[&HostData]) -> Result<HostData> {
+        let n = var.size();
+        let ty = var.ty();
+        let op = var.op();
+        let unsupported = || anyhow!("{op:?} is not supported for {ty:?} on the cpu backend!");
+        let arg = |i: usize| -> Result<&HostData> {
+            args.get(i)
+                .copied()
+                .ok_or_else(|| anyhow!("{op:?} is missing operand {i}!"))
+        };
+        Ok(match &op {
+            Op::Data => bail!(
+                "Variable #{} holds device memory, which the cpu backend can not read!",
+                var.id()
+            ),
+            Op::Literal => {
+                let bits = var
+                    .literal()
+                    .ok_or_else(|| anyhow!("Literal without a value!"))?;
+                HostData::from_bits(&ty, std::iter::repeat(bits).take(n))?
+            }
+            Op::Idx => HostData::U32((0..n as u32).collect()),
+
+            Op::Add => binary!(n, arg(0)?, arg(1)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => add,
+                [F32, F64] => |a, b| a + b)
+            .ok_or_else(unsupported)?,
+            Op::Sub => binary!(n, arg(0)?, arg(1)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => |a, b| a.wrapping_sub(b),
+                [F32, F64] => |a, b| a - b)
+            .ok_or_else(unsupported)?,
+            Op::Mul => binary!(n, arg(0)?, arg(1)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => |a, b| a.wrapping_mul(b),
+                [F32, F64] => |a, b| a * b)
+            .ok_or_else(unsupported)?,
+            Op::Div => binary!(n, arg(0)?, arg(1)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => |a, b| a.checked_div(b).unwrap_or(0),
+                [F32, F64] => |a, b| a / b)
+            .ok_or_else(unsupported)?,
+            Op::Mod => binary!(n, arg(0)?, arg(1)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => |a, b| a.checked_rem(b).unwrap_or(0),
+                [F32, F64] => |a, b| a % b)
+            .ok_or_else(unsupported)?,
+            Op::Min => binary!(n, arg(0)?, arg(1)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => std::cmp::min,
+                [F32, F64] => |a, b| a.min(b))
+            .ok_or_else(unsupported)?,
+            Op::Max => binary!(n, arg(0)?, arg(1)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => std::cmp::max,
+                [F32, F64] => |a, b| a.max(b))
+            .ok_or_else(unsupported)?,
+            Op::And => binary!(n, arg(0)?, arg(1)?,
+                [Bool, I8, U8, I16, U16, I32, U32, I64, U64] => |a, b| a & b)
+            .ok_or_else(unsupported)?,
+            Op::Or => binary!(n, arg(0)?, arg(1)?,
+                [Bool, I8, U8, I16, U16, I32, U32, I64, U64] => |a, b| a | b)
+            .ok_or_else(unsupported)?,
+            Op::Xor => binary!(n, arg(0)?, arg(1)?,
+                [Bool, I8, U8, I16, U16, I32, U32, I64, U64] => |a, b| a ^ b)
+            .ok_or_else(unsupported)?,
+            Op::Shl => binary!(n, arg(0)?, arg(1)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => |a, b| a.wrapping_shl(b as u32))
+            .ok_or_else(unsupported)?,
+            Op::Shr => binary!(n, arg(0)?, arg(1)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => |a, b| a.wrapping_shr(b as u32))
+            .ok_or_else(unsupported)?,
+
+            Op::Eq => compare!(n, arg(0)?, arg(1)?, |a, b| a == b).ok_or_else(unsupported)?,
+            Op::Neq => compare!(n, arg(0)?, arg(1)?, |a, b| a != b).ok_or_else(unsupported)?,
+            Op::Lt => compare!(n, arg(0)?, arg(1)?, |a, b| a < b).ok_or_else(unsupported)?,
+            Op::Le => compare!(n, arg(0)?, arg(1)?, |a, b| a <= b).ok_or_else(unsupported)?,
+            Op::Gt => compare!(n, arg(0)?, arg(1)?, |a, b| a > b).ok_or_else(unsupported)?,
+            Op::Ge => compare!(n, arg(0)?, arg(1)?, |a, b| a >= b).ok_or_else(unsupported)?,
+
+            Op::Fma => {
+                let mul = binary!(n, arg(0)?, arg(1)?,
+                    [I8, U8, I16, U16, I32, U32, I64, U64] => |a, b| a.wrapping_mul(b),
+                    [F32, F64] => |a, b| a * b)
+                .ok_or_else(unsupported)?;
+                binary!(n, &mul, arg(2)?,
+                    [I8, U8, I16, U16, I32, U32, I64, U64] => add,
+                    [F32, F64] => |a, b| a + b)
+                .ok_or_else(unsupported)?
+            }
+            Op::Select => {
+                let mask = mask(Some(arg(0)?), n)?;
+                let (a, b) = (arg(1)?.bits(), arg(2)?.bits());
+                let lanes = (0..n).map(|i| if mask[i] { at(&a, i) } else { at(&b, i) });
+                HostData::from_bits(&ty, lanes)?
+            }
+
+            Op::Neg => unary!(n, arg(0)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => |a| a.wrapping_neg(),
+                [F32, F64] => |a| -a)
+            .ok_or_else(unsupported)?,
+            Op::Not => unary!(n, arg(0)?,
+                [Bool, I8, U8, I16, U16, I32, U32, I64, U64] => |a| !a)
+            .ok_or_else(unsupported)?,
+            Op::Abs => unary!(n, arg(0)?,
+                [I8, I16, I32, I64] => |a| a.wrapping_abs(),
+                [U8, U16, U32, U64] => |a| a,
+                [F32, F64] => |a| a.abs())
+            .ok_or_else(unsupported)?,
+            Op::Ceil => unary!(n, arg(0)?, [F32, F64] => |a| a.ceil()).ok_or_else(unsupported)?,
+            Op::Floor => unary!(n, arg(0)?, [F32, F64] => |a| a.floor()).ok_or_else(unsupported)?,
+            Op::Trunc => unary!(n, arg(0)?, [F32, F64] => |a| a.trunc()).ok_or_else(unsupported)?,
+            Op::Rcp => unary!(n, arg(0)?, [F32, F64] => |a| a.recip()).ok_or_else(unsupported)?,
+            Op::Rsqrt => {
+                unary!(n, arg(0)?, [F32, F64] => |a| a.sqrt().recip()).ok_or_else(unsupported)?
+            }
+            Op::Sin => unary!(n, arg(0)?, [F32, F64] => |a| a.sin()).ok_or_else(unsupported)?,
+            Op::Cos => unary!(n, arg(0)?, [F32, F64] => |a| a.cos()).ok_or_else(unsupported)?,
+            Op::Exp2 => unary!(n, arg(0)?, [F32, F64] => |a| a.exp2()).ok_or_else(unsupported)?,
+            Op::Log2 => unary!(n, arg(0)?, [F32, F64] => |a| a.log2()).ok_or_else(unsupported)?,
+            Op::Popc => unary!(n, arg(0)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => |a| a.count_ones() as _)
+            .ok_or_else(unsupported)?,
+            Op::Clz => unary!(n, arg(0)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => |a| a.leading_zeros() as _)
+            .ok_or_else(unsupported)?,
+            Op::Ctz => unary!(n, arg(0)?,
+                [I8, U8, I16, U16, I32, U32, I64, U64] => |a| a.trailing_zeros() as _)
+            .ok_or_else(unsupported)?,
+
+            Op::Cast => cast(n, arg(0)?, &ty)?,
+            Op::Bitcast => {
+                let bits = arg(0)?.bits();
+                HostData::from_bits(&ty, (0..n).map(|i| at(&bits, i)))?
+            }
+
+            // Operands: source, index and optionally mask.
+            Op::Gather => {
+                let mask = mask(args.get(2).copied(), n)?;
+                gather(arg(0)?, &indices(arg(1)?, n)?, &mask)?
+            }
+            // Operands: source, target, index and optionally mask. The target is
+            // modified in place and keeps its new value.
+            Op::Scatter | Op::ScatterReduce(_) => {
+                let reduce = match &op {
+                    Op::ScatterReduce(op) => Some(op),
+                    _ => None,
+                };
+                let n = arg(0)?.size().max(arg(2)?.size());
+                let mask = mask(args.get(3).copied(), n)?;
+                let mut dst = arg(1)?.clone();
+                scatter(arg(0)?, &mut dst, &indices(arg(2)?, n)?, &mask, reduce)?;
+                self.values
+                    .lock()
+                    .insert(deps[1].id(), (deps[1].clone(), dst));
+                HostData::Bool(vec![])
+            }
+            // Operand: mask. Returns the indices of the active lanes.
+            Op::Compress => {
+                let mask = mask(Some(arg(0)?), arg(0)?.size())?;
+                HostData::U32(
+                    (0..mask.len() as u32)
+                        .filter(|&i| mask[i as usize])
+                        .collect(),
+                )
+            }
+            _ => bail!("{op:?} is not supported on the cpu backend!"),
+        })
+    }
+}
@@ -0,0 +1,570 @@
+//! Saving and loading Vars.
+//! `.npy` and `.npz` files are read and written natively, without numpy. Every
+//! other extension uses the native container below, which also keeps texture
+//! shapes, tensor shapes and the geometry of `AccelDesc`s. All numbers are little
+//! endian:
+//!
+//! ```text
+//! file:     "PJIT" version:u32 single:u8 n_entries:u32 entry*
+//! entry:    name_len:u32 name kind:u8 (array | texture | accel)
+//! array:    shape data
+//! texture:  shape n_channels:u64 data
+//! accel:    n_geometries:u32 (kind:u8 n_vars:u8 data*)*
+//!           n_instances:u32 instance*
+//! instance: geometry:u64 transform:f32*12 hit_group:u32 visibility_mask:u8
+//!           instance_id:u32 flags:u32 sbt_offset:u32
+//! shape:    ndim:u32 dim:u64*
+//! data:     type:u8 len:u64 value*
+//! ```
+use crate::funcs::{AccelDesc, GeometryDesc, InstanceDesc};
+use crate::host::HostData;
+use crate::var::Var;
+use anyhow::{anyhow, bail, Result};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use rjit::VarType;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"PJIT";
+const VERSION: u32 = 1;
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// Types in the order of their codes in the native format, with their widths in
+/// bytes and their numpy type codes.
+pub const TYPES: [(VarType, usize, &str); 11] = [
+    (VarType::Bool, 1, "b1"),
+    (VarType::I8, 1, "i1"),
+    (VarType::U8, 1, "u1"),
+    (VarType::I16, 2, "i2"),
+    (VarType::U16, 2, "u2"),
+    (VarType::I32, 4, "i4"),
+    (VarType::U32, 4, "u4"),
+    (VarType::I64, 8, "i8"),
+    (VarType::U64, 8, "u8"),
+    (VarType::F32, 4, "f4"),
+    (VarType::F64, 8, "f8"),
+];
+
+/// A saved value, read back from the device.
+enum Entry {
+    /// A Var, or a tensor if `shape` has more than one dimension.
+    Array { data: HostData, shape: Vec<usize> },
+    Texture {
+        data: HostData,
+        shape: Vec<usize>,
+        n_channels: usize,
+    },
+    /// The geometry and instances of an `AccelDesc`. Hit and miss groups are not
+    /// saved.
+    /// Geometries are stored by their `GeometryDesc::kind` and Vars.
+    Accel {
+        geometries: Vec<(u8, Vec<HostData>)>,
+        instances: Vec<InstanceDesc>,
+    },
+}
+
+impl Entry {
+    /// Reads a Var, texture, tensor (an object with `data` and `shape` attributes)
+    /// or `AccelDesc` back from the device.
+    fn from_py(py: Python, obj: &PyAny) -> PyResult<Self> {
+        let read = |var: &Var| -> PyResult<HostData> {
+            var.eval(py)?;
+            Ok(var.read(py)?)
+        };
+        if let Ok(var) = obj.extract::<Var>() {
+            if let Some((shape, n_channels)) = var.0.texture_shape() {
+                let data = read(&Var(var.0.tex_to_buffer()?))?;
+                return Ok(Self::Texture {
+                    data,
+                    shape,
+                    n_channels,
+                });
+            }
+            return Ok(Self::Array {
+                shape: vec![var.0.size()],
+                data: read(&var)?,
+            });
+        }
+        if let Ok(desc) = obj.extract::<PyRef<AccelDesc>>() {
+            let geometries = desc
+                .geometries
+                .iter()
+                .map(|g| {
+                    let data = g.vars().into_iter().map(read).collect::<PyResult<_>>()?;
+                    Ok((g.kind(), data))
+                })
+                .collect::<PyResult<_>>()?;
+            let instances = desc.instances.clone();
+            return Ok(Self::Accel {
+                geometries,
+                instances,
+            });
+        }
+        if let (Ok(data), Ok(shape)) = (obj.getattr("data"), obj.getattr("shape")) {
+            let data = read(&data.extract::<Var>()?)?;
+            let shape = shape.extract::<Vec<usize>>()?;
+            if shape.iter().product::<usize>() != data.size() {
+                return Err(PyErr::new::<PyValueError, _>(format!(
+                    "Tensor of shape {shape:?} can not hold {} values!",
+                    data.size()
+                )));
+            }
+            return Ok(Self::Array { data, shape });
+        }
+        Err(PyErr::new::<PyTypeError, _>(format!(
+            "Can not save objects of type {}!",
+            obj.get_type().name()?
+        )))
+    }
+    /// Uploads the entry to the current context. Tensors become namespaces with
+    /// `data` and `shape` attributes.
+    fn into_object(self, py: Python) -> PyResult<PyObject> {
+        Ok(match self {
+            Self::Array { data, shape } => {
+                let var = Var(data.to_var()?);
+                if shape.len() == 1 {
+                    var.into_py(py)
+                } else {
+                    tensor(py, var, shape)?
+                }
+            }
+            Self::Texture {
+                data,
+                shape,
+                n_channels,
+            } => Var(data.to_var()?.to_texture(&shape, n_channels)?).into_py(py),
+            Self::Accel {
+                geometries,
+                instances,
+            } => {
+                let mut desc = AccelDesc::new();
+                for (kind, data) in geometries {
+                    let vars = data
+                        .iter()
+                        .map(|d| Ok(Var(d.to_var()?)))
+                        .collect::<Result<_>>()?;
+                    let geometry = GeometryDesc::from_vars(kind, vars)
+                        .ok_or_else(|| anyhow!("Invalid geometry of kind {kind}!"))?;
+                    desc.geometries.push(geometry);
+                }
+                desc.instances = instances;
+                Py::new(py, desc)?.into_py(py)
+            }
+        })
+    }
+}
+
+fn tensor(py: Python, data: Var, shape: Vec<usize>) -> PyResult<PyObject> {
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("data", data)?;
+    kwargs.set_item("shape", shape)?;
+    Ok(py
+        .import("types")?
+        .getattr("SimpleNamespace")?
+        .call((), Some(kwargs))?
+        .into())
+}
+
+fn write_shape(buf: &mut Vec<u8>, shape: &[usize]) {
+    buf.extend((shape.len() as u32).to_le_bytes());
+    for dim in shape {
+        buf.extend((*dim as u64).to_le_bytes());
+    }
+}
+/// Appends the values of `data` without a header.
+fn write_values(buf: &mut Vec<u8>, data: &HostData) {
+    let width = TYPES.iter().find(|(ty, ..)| *ty == data.ty()).unwrap().1;
+    for bits in data.bits() {
+        buf.extend(&bits.to_le_bytes()[..width]);
+    }
+}
+pub fn write_data(buf: &mut Vec<u8>, data: &HostData) {
+    let code = TYPES.iter().position(|(ty, ..)| *ty == data.ty()).unwrap();
+    buf.push(code as u8);
+    buf.extend((data.size() as u64).to_le_bytes());
+    write_values(buf, data);
+}
+
+fn write(entries: &[(String, Entry)], single: bool) -> Vec<u8> {
+    let mut buf = MAGIC.to_vec();
+    buf.extend(VERSION.to_le_bytes());
+    buf.push(single as u8);
+    buf.extend((entries.len() as u32).to_le_bytes());
+    for (name, entry) in entries {
+        buf.extend((name.len() as u32).to_le_bytes());
+        buf.extend(name.as_bytes());
+        match entry {
+            Entry::Array { data, shape } => {
+                buf.push(0);
+                write_shape(&mut buf, shape);
+                write_data(&mut buf, data);
+            }
+            Entry::Texture {
+                data,
+                shape,
+                n_channels,
+            } => {
+                buf.push(1);
+                write_shape(&mut buf, shape);
+                buf.extend((*n_channels as u64).to_le_bytes());
+                write_data(&mut buf, data);
+            }
+            Entry::Accel {
+                geometries,
+                instances,
+            } => {
+                buf.push(2);
+                buf.extend((geometries.len() as u32).to_le_bytes());
+                for (kind, data) in geometries {
+                    buf.push(*kind);
+                    buf.push(data.len() as u8);
+                    for data in data {
+                        write_data(&mut buf, data);
+                    }
+                }
+                buf.extend((instances.len() as u32).to_le_bytes());
+                for instance in instances {
+                    buf.extend((instance.geometry as u64).to_le_bytes());
+                    for x in instance.transform {
+                        buf.extend(x.to_le_bytes());
+                    }
+                    buf.extend(instance.hit_group.to_le_bytes());
+                    buf.push(instance.visibility_mask);
+                    buf.extend(instance.instance_id.to_le_bytes());
+                    buf.extend(instance.flags.to_le_bytes());
+                    buf.extend(instance.sbt_offset.to_le_bytes());
+                }
+            }
+        }
+    }
+    buf
+}
+
+/// Reads the native format from a byte buffer.
+pub struct Reader<'a>(pub &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.0.len() < n {
+            bail!("The file is truncated!");
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+    fn shape(&mut self) -> Result<Vec<usize>> {
+        let ndim = self.u32()?;
+        (0..ndim).map(|_| Ok(self.u64()? as usize)).collect()
+    }
+    pub fn data(&mut self) -> Result<HostData> {
+        let code = self.u8()? as usize;
+        let (ty, ..) = TYPES
+            .get(code)
+            .ok_or_else(|| anyhow!("Unknown type code {code}!"))?;
+        let len = self.u64()? as usize;
+        self.values(ty, len)
+    }
+    /// Reads `len` values of type `ty` without a header.
+    fn values(&mut self, ty: &VarType, len: usize) -> Result<HostData> {
+        let width = TYPES.iter().find(|(t, ..)| t == ty).unwrap().1;
+        let bytes = self.take(
+            len.checked_mul(width)
+                .ok_or_else(|| anyhow!("Corrupt length!"))?,
+        )?;
+        HostData::from_bits(
+            ty,
+            bytes.chunks_exact(width).map(|b| {
+                let mut bits = [0u8; 8];
+                bits[..b.len()].copy_from_slice(b);
+                u64::from_le_bytes(bits)
+            }),
+        )
+    }
+    fn entry(&mut self) -> Result<(String, Entry)> {
+        let len = self.u32()? as usize;
+        let name = String::from_utf8(self.take(len)?.to_vec())?;
+        let entry = match self.u8()? {
+            0 => Entry::Array {
+                shape: self.shape()?,
+                data: self.data()?,
+            },
+            1 => Entry::Texture {
+                shape: self.shape()?,
+                n_channels: self.u64()? as usize,
+                data: self.data()?,
+            },
+            2 => {
+                let n_geometries = self.u32()?;
+                let geometries = (0..n_geometries)
+                    .map(|_| {
+                        let kind = self.u8()?;
+                        let n_vars = self.u8()?;
+                        let data = (0..n_vars).map(|_| self.data()).collect::<Result<_>>()?;
+                        Ok((kind, data))
+                    })
+                    .collect::<Result<_>>()?;
+                let n_instances = self.u32()?;
+                let instances = (0..n_instances)
+                    .map(|_| {
+                        let geometry = self.u64()? as usize;
+                        let mut transform = [0.; 12];
+                        for x in &mut transform {
+                            *x = self.f32()?;
+                        }
+                        Ok(InstanceDesc {
+                            geometry,
+                            transform,
+                            hit_group: self.u32()?,
+                            visibility_mask: self.u8()?,
+                            instance_id: self.u32()?,
+                            flags: self.u32()?,
+                            sbt_offset: self.u32()?,
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                Entry::Accel {
+                    geometries,
+                    instances,
+                }
+            }
+            kind => bail!("Unknown entry kind {kind}!"),
+        };
+        let (data, len) = match &entry {
+            Entry::Array { data, shape } => (data, shape.iter().product::<usize>()),
+            Entry::Texture {
+                data,
+                shape,
+                n_channels,
+            } => (data, shape.iter().product::<usize>() * n_channels),
+            Entry::Accel { .. } => return Ok((name, entry)),
+        };
+        if data.size() != len {
+            bail!(
+                "Entry {name} holds {} values, but its shape needs {len}!",
+                data.size()
+            );
+        }
+        Ok((name, entry))
+    }
+}
+
+/// Reads the native format, returning its entries and whether they were saved from
+/// a single value instead of a dict.
+fn read(bytes: &[u8]) -> Result<(Vec<(String, Entry)>, bool)> {
+    let mut reader = Reader(bytes);
+    if reader.take(4)? != MAGIC {
+        bail!("Not a pyjit file!");
+    }
+    let version = reader.u32()?;
+    if version != VERSION {
+        bail!("Unsupported pyjit file version {version}, expected {VERSION}!");
+    }
+    let single = reader.u8()? != 0;
+    let n = reader.u32()?;
+    let entries = (0..n).map(|_| reader.entry()).collect::<Result<_>>()?;
+    Ok((entries, single))
+}
+
+/// The entries to save, the items of a dict or `obj` itself named `arr_0`, and
+/// whether `obj` is a single value.
+fn entries(py: Python, obj: &PyAny) -> PyResult<(Vec<(String, Entry)>, bool)> {
+    match obj.downcast::<PyDict>() {
+        Ok(dict) => {
+            let entries = dict
+                .iter()
+                .map(|(k, v)| Ok((k.extract::<String>()?, Entry::from_py(py, v)?)))
+                .collect::<PyResult<_>>()?;
+            Ok((entries, false))
+        }
+        Err(_) => Ok((vec![("arr_0".into(), Entry::from_py(py, obj)?)], true)),
+    }
+}
+
+/// Writes an entry as a `.npy` file, version 1.0. Textures get their channels as
+/// the last dimension.
+fn to_npy(entry: Entry) -> Result<Vec<u8>> {
+    let (data, shape) = match entry {
+        Entry::Array { data, shape } => (data, shape),
+        Entry::Texture {
+            data,
+            mut shape,
+            n_channels,
+        } => {
+            shape.push(n_channels);
+            (data, shape)
+        }
+        Entry::Accel { .. } => bail!("An AccelDesc can only be saved in the native format!"),
+    };
+    let (_, width, code) = TYPES.iter().find(|(ty, ..)| *ty == data.ty()).unwrap();
+    let order = if *width == 1 { '|' } else { '<' };
+    let dims = shape.iter().map(|d| format!("{d},")).collect::<String>();
+    let mut header =
+        format!("{{'descr': '{order}{code}', 'fortran_order': False, 'shape': ({dims}), }}");
+    // The header is padded with spaces and ends in a newline, so that the data is
+    // aligned to 64 bytes.
+    let len = NPY_MAGIC.len() + 4 + header.len() + 1;
+    header.extend(std::iter::repeat(' ').take((64 - len % 64) % 64));
+    header.push('\n');
+
+    let mut buf = NPY_MAGIC.to_vec();
+    buf.extend([1, 0]);
+    buf.extend((header.len() as u16).to_le_bytes());
+    buf.extend(header.as_bytes());
+    write_values(&mut buf, &data);
+    Ok(buf)
+}
+
+/// Returns the value of `key` in the header dict of a `.npy` file, up to the next
+/// top-level comma.
+fn npy_field<'a>(header: &'a str, key: &str) -> Result<&'a str> {
+    let start = header
+        .find(&format!("'{key}'"))
+        .ok_or_else(|| anyhow!("The .npy header has no {key}!"))?
+        + key.len()
+        + 2;
+    let value = header[start..]
+        .trim_start()
+        .trim_start_matches(':')
+        .trim_start();
+    let end = if value.starts_with('(') {
+        value.find(')').map(|i| i + 1)
+    } else {
+        value.find(|c| c == ',' || c == '}')
+    };
+    Ok(value[..end.ok_or_else(|| anyhow!("Invalid .npy header!"))?].trim())
+}
+
+/// Reads a `.npy` file of versions 1 to 3 holding a little endian, C ordered array.
+fn from_npy(bytes: &[u8]) -> Result<Entry> {
+    let mut reader = Reader(bytes);
+    if reader.take(NPY_MAGIC.len())? != NPY_MAGIC {
+        bail!("Not a .npy file!");
+    }
+    let (major, _minor) = (reader.u8()?, reader.u8()?);
+    let header_len = match major {
+        1 => u16::from_le_bytes(reader.take(2)?.try_into()?) as usize,
+        2 | 3 => reader.u32()? as usize,
+        version => bail!("Unsupported .npy version {version}!"),
+    };
+    let header = std::str::from_utf8(reader.take(header_len)?)?;
+
+    let descr = npy_field(header, "descr")?.trim_matches(|c| c == '\'' || c == '"');
+    let code = match descr.as_bytes().first() {
+        Some(b'<' | b'|') => &descr[1..],
+        _ => bail!("Can not load arrays of dtype {descr}, only little endian is supported!"),
+    };
+    let (ty, ..) = TYPES
+        .iter()
+        .find(|(.., c)| *c == code)
+        .ok_or_else(|| anyhow!("Can not load arrays of dtype {descr}!"))?;
+    if npy_field(header, "fortran_order")? != "False" {
+        bail!("Can not load arrays in Fortran order!");
+    }
+    let shape = npy_field(header, "shape")?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|d| Ok(d.parse::<usize>()?))
+        .collect::<Result<Vec<_>>>()?;
+
+    let len = shape.iter().product::<usize>();
+    let width = TYPES.iter().find(|(t, ..)| t == ty).unwrap().1;
+    if Some(reader.0.len()) != len.checked_mul(width) {
+        bail!(
+            "The .npy file holds {} bytes, its shape {shape:?} needs {}!",
+            reader.0.len(),
+            len.saturating_mul(width)
+        );
+    }
+    let data = reader.values(ty, len)?;
+    Ok(Entry::Array { data, shape })
+}
+
+/// Writes entries as a `.npz` file, an uncompressed zip archive of `.npy` files.
+fn to_npz(entries: Vec<(String, Entry)>) -> Result<Vec<u8>> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (name, entry) in entries {
+        zip.start_file(format!("{name}.npy"), options)?;
+        zip.write_all(&to_npy(entry)?)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Reads a `.npz` file, compressed or not.
+fn from_npz(bytes: Vec<u8>) -> Result<Vec<(String, Entry)>> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(bytes))?;
+    (0..zip.len())
+        .map(|i| {
+            let mut file = zip.by_index(i)?;
+            let name = file.name().trim_end_matches(".npy").to_string();
+            let mut bytes = vec![];
+            file.read_to_end(&mut bytes)?;
+            let entry = from_npy(&bytes).map_err(|err| anyhow!("{name}: {err}"))?;
+            Ok((name, entry))
+        })
+        .collect()
+}
+
+/// Saves a Var, texture, tensor or `AccelDesc`, or a dict of them, to `path`.
+/// `.npy` files hold a single array and `.npz` files a dict of arrays, a single
+/// value is saved as `arr_0` like `numpy.savez` does. Any other extension uses the
+/// native format, which also keeps texture and accel metadata.
+#[pyfunction]
+pub fn save(py: Python, path: &str, obj: &PyAny) -> PyResult<()> {
+    let (entries, single) = entries(py, obj)?;
+    let bytes = match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("npy") => {
+            let [(_, entry)]: [_; 1] = entries.try_into().map_err(|_| {
+                PyErr::new::<PyValueError, _>("A .npy file can only hold a single array!")
+            })?;
+            to_npy(entry).map_err(|err| PyErr::new::<PyTypeError, _>(err.to_string()))?
+        }
+        Some("npz") => {
+            to_npz(entries).map_err(|err| PyErr::new::<PyTypeError, _>(err.to_string()))?
+        }
+        _ => write(&entries, single),
+    };
+    py.allow_threads(|| std::fs::write(path, bytes))?;
+    Ok(())
+}
+
+/// Loads a file written by `save`, or by numpy, into the current context. Returns
+/// a single value for `.npy` files and for native files saved from a single value,
+/// and a dict otherwise.
+#[pyfunction]
+pub fn load(py: Python, path: &str) -> PyResult<PyObject> {
+    let bytes = py.allow_threads(|| std::fs::read(path))?;
+    let (entries, single) = match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("npy") => return from_npy(&bytes)?.into_object(py),
+        Some("npz") => (from_npz(bytes)?, false),
+        _ => read(&bytes)?,
+    };
+    if single {
+        if let Some((_, entry)) = entries.into_iter().next() {
+            return entry.into_object(py);
+        }
+        return Err(PyErr::new::<PyValueError, _>("The file holds no value!"));
+    }
+    let res = PyDict::new(py);
+    for (name, entry) in entries {
+        res.set_item(name, entry.into_object(py)?)?;
+    }
+    Ok(res.into())
+}
@@ -3,7 +3,24 @@ use pyo3::prelude::*;
 use self::funcs::*;
 use self::var::*;
 
+mod accel;
+mod backend;
+mod bvh;
+mod cache;
+mod context;
+mod debug;
+mod dispatch;
+mod freeze;
 mod funcs;
+mod future;
+mod host;
+mod interp;
+mod io;
+mod logging;
+mod profile;
+mod record;
+mod stats;
+mod tree;
 mod var;
 
 // /// Formats the sum of two numbers as string.
@@ -14,11 +31,21 @@ mod var;
 
 /// A Python module implemented in Rust.
 #[pymodule]
-fn pyjit(_py: Python, m: &PyModule) -> PyResult<()> {
-    pyo3_log::init();
+fn pyjit(py: Python, m: &PyModule) -> PyResult<()> {
+    logging::init(py)?;
+    cache::init();
     // m.add_function(wrap_pyfunction!(sum_as_string, m)?)?;
     m.add_class::<Var>()?;
     m.add_class::<AccelDesc>()?;
+    m.add_class::<accel::Accel>()?;
+    m.add_class::<context::Context>()?;
+    m.add_class::<backend::DeviceInfo>()?;
+    m.add_class::<future::Future>()?;
+    m.add_class::<debug::Kernel>()?;
+    m.add_class::<stats::Stats>()?;
+    m.add_class::<profile::Profile>()?;
+    m.add_class::<record::Recording>()?;
+    m.add_class::<freeze::Frozen>()?;
 
     m.add_function(wrap_pyfunction!(funcs::bool, m)?)?;
     m.add_function(wrap_pyfunction!(funcs::i8, m)?)?;
@@ -37,8 +64,43 @@ fn pyjit(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(funcs::texture, m)?)?;
     m.add_function(wrap_pyfunction!(funcs::accel, m)?)?;
 
-    m.add_function(wrap_pyfunction!(funcs::set_backend, m)?)?;
+    m.add_function(wrap_pyfunction!(io::save, m)?)?;
+    m.add_function(wrap_pyfunction!(io::load, m)?)?;
+    m.add_function(wrap_pyfunction!(record::record, m)?)?;
+    m.add_function(wrap_pyfunction!(freeze::freeze, m)?)?;
+
+    m.add_function(wrap_pyfunction!(backend::set_backend, m)?)?;
+    m.add_function(wrap_pyfunction!(backend::backend, m)?)?;
+    m.add_function(wrap_pyfunction!(backend::available_backends, m)?)?;
+    m.add_function(wrap_pyfunction!(backend::device_info, m)?)?;
+
+    m.add_function(wrap_pyfunction!(cache::set_cache_dir, m)?)?;
+    m.add_function(wrap_pyfunction!(cache::cache_dir, m)?)?;
+    m.add_function(wrap_pyfunction!(cache::clear_cache, m)?)?;
+
+    m.add_function(wrap_pyfunction!(debug::kernel_source, m)?)?;
+    m.add_function(wrap_pyfunction!(debug::kernel_history, m)?)?;
+    m.add_function(wrap_pyfunction!(debug::graphviz, m)?)?;
+
+    m.add_function(wrap_pyfunction!(stats::stats, m)?)?;
+    m.add_function(wrap_pyfunction!(stats::reset_stats, m)?)?;
+
+    m.add_function(wrap_pyfunction!(profile::profile_start, m)?)?;
+    m.add_function(wrap_pyfunction!(profile::profile_stop, m)?)?;
+
+    m.add_function(wrap_pyfunction!(logging::set_log_level, m)?)?;
+
+    m.add_function(wrap_pyfunction!(dispatch::register, m)?)?;
+    m.add_function(wrap_pyfunction!(dispatch::unregister, m)?)?;
+    m.add_function(wrap_pyfunction!(dispatch::switch, m)?)?;
+    m.add_function(wrap_pyfunction!(dispatch::dispatch, m)?)?;
 
     m.add_function(wrap_pyfunction!(funcs::eval, m)?)?;
+    m.add_function(wrap_pyfunction!(future::eval_async, m)?)?;
+
+    m.add_function(wrap_pyfunction!(tree::schedule, m)?)?;
+    m.add_function(wrap_pyfunction!(tree::select, m)?)?;
+    m.add_function(wrap_pyfunction!(tree::gather, m)?)?;
+    m.add_function(wrap_pyfunction!(tree::scatter, m)?)?;
     Ok(())
 }
@@ -0,0 +1,94 @@
+use log::LevelFilter;
+use once_cell::sync::OnceCell;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use pyo3_log::{Caching, ResetHandle};
+use rjit::Trace;
+
+/// Subsystems whose log output can be filtered individually. rjit logs them with the
+/// targets `rjit::<subsystem>`, which show up as the python loggers `rjit.<subsystem>`.
+const SUBSYSTEMS: [&str; 4] = ["trace", "compile", "launch", "memory"];
+
+static RESET_HANDLE: OnceCell<ResetHandle> = OnceCell::new();
+
+/// Bridges rust logging to python `logging`.
+/// Only loggers are cached, levels are looked up for every record so that changes to
+/// the python logging configuration after import take effect.
+pub fn init(py: Python) -> PyResult<()> {
+    let handle = pyo3_log::Logger::new(py, Caching::Loggers)?
+        .filter(LevelFilter::Trace)
+        .install()
+        .map_err(|err| PyErr::new::<PyValueError, _>(err.to_string()))?;
+    RESET_HANDLE.set(handle).ok();
+    Ok(())
+}
+
+fn loggers(target: Option<&str>) -> PyResult<Vec<String>> {
+    match target {
+        None => Ok(vec!["rjit".into(), "pyjit".into()]),
+        Some(target) if SUBSYSTEMS.contains(&target) => {
+            Ok(vec![format!("rjit.{target}"), format!("pyjit.{target}")])
+        }
+        Some(target) => Err(PyErr::new::<PyValueError, _>(format!(
+            "Unknown log target {target}, expected one of {SUBSYSTEMS:?}!"
+        ))),
+    }
+}
+
+/// Sets the level of the python loggers receiving the output of `target`, which is
+/// one of "trace", "compile", "launch" or "memory", or all of them if `None`.
+/// `level` is a python logging level such as `logging.DEBUG` or `"debug"`.
+#[pyfunction]
+pub fn set_log_level(py: Python, level: &PyAny, target: Option<&str>) -> PyResult<()> {
+    let level = match level.extract::<&str>() {
+        Ok(level) => level.to_uppercase().into_py(py),
+        Err(_) => level.into_py(py),
+    };
+    let logging = py.import("logging")?;
+    for name in loggers(target)? {
+        logging
+            .call_method1("getLogger", (name,))?
+            .call_method1("setLevel", (level.clone_ref(py),))?;
+    }
+    if let Some(handle) = RESET_HANDLE.get() {
+        handle.reset();
+    }
+    Ok(())
+}
+
+/// Emits a record on the `pyjit.launch` logger for every kernel `ir` launched after
+/// the first `n_kernels`. The kernel hash and timings are attached as `kernel_hash`,
+/// `compile_time` and `run_time` attributes of the record.
+pub fn log_launches(ir: &Trace, n_kernels: usize) {
+    Python::with_gil(|py| -> PyResult<()> {
+        let logger = py
+            .import("logging")?
+            .call_method1("getLogger", ("pyjit.launch",))?;
+        // 10 is `logging.DEBUG`.
+        if !logger.call_method1("isEnabledFor", (10,))?.is_true()? {
+            return Ok(());
+        }
+        for kernel in ir.kernel_history_since(n_kernels) {
+            let extra = PyDict::new(py);
+            extra.set_item("kernel_hash", format!("{:016x}", kernel.hash))?;
+            extra.set_item("compile_time", kernel.compile_time.as_secs_f64())?;
+            extra.set_item("run_time", kernel.execution_time.as_secs_f64())?;
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("extra", extra)?;
+            logger.call_method(
+                "debug",
+                (
+                    "Launched kernel %016x of size %d (compile %.3fms, run %.3fms)",
+                    kernel.hash,
+                    kernel.size,
+                    kernel.compile_time.as_secs_f64() * 1e3,
+                    kernel.execution_time.as_secs_f64() * 1e3,
+                ),
+                Some(kwargs),
+            )?;
+        }
+        Ok(())
+    })
+    .unwrap_or_else(|err| log::warn!("Could not log kernel launches: {err}"));
+}
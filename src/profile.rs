@@ -0,0 +1,193 @@
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use pyo3::prelude::*;
+use rjit::Trace;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+static PROFILER: Lazy<Mutex<Option<Profiler>>> = Lazy::new(|| Mutex::new(None));
+
+/// A complete ("X") event of the chrome tracing format.
+struct Event {
+    name: String,
+    cat: &'static str,
+    start: Duration,
+    dur: Duration,
+    /// Whether the event was laid out from durations instead of being timed, which
+    /// is shown as the `synthetic` argument of the event.
+    synthetic: bool,
+    /// Hash, compile and run time of the kernel of a compile or launch event, shown
+    /// as the `kernel_hash`, `compile_time` and `run_time` arguments, in seconds.
+    kernel: Option<(u64, Duration, Duration)>,
+}
+
+struct Profiler {
+    start: Instant,
+    /// End of the last recorded eval, i.e. the start of the current trace building phase.
+    last_eval: Duration,
+    events: Vec<Event>,
+}
+
+impl Profiler {
+    fn to_json(&self) -> String {
+        let pid = std::process::id();
+        let events = self
+            .events
+            .iter()
+            .map(|e| {
+                let kernel = e.kernel.map_or(String::new(), |(hash, compile, run)| {
+                    format!(
+                        ",\"kernel_hash\":\"{:016x}\",\"compile_time\":{},\"run_time\":{}",
+                        hash,
+                        compile.as_secs_f64(),
+                        run.as_secs_f64()
+                    )
+                });
+                format!(
+                    "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":{},\"tid\":0,\"args\":{{\"synthetic\":{}{}}}}}",
+                    e.name.replace('\\', "\\\\").replace('"', "\\\""),
+                    e.cat,
+                    e.start.as_micros(),
+                    e.dur.as_micros(),
+                    pid,
+                    e.synthetic,
+                    kernel
+                )
+            })
+            .collect::<Vec<_>>();
+        format!("{{\"traceEvents\":[\n{}\n]}}\n", events.join(",\n"))
+    }
+}
+
+/// Runs `f` and records it as an event of category `cat`, if profiling is enabled.
+pub fn record<T>(name: &str, cat: &'static str, f: impl FnOnce() -> T) -> T {
+    let Some(start) = PROFILER.lock().as_ref().map(|p| p.start) else {
+        return f();
+    };
+    let begin = start.elapsed();
+    let res = f();
+    let dur = start.elapsed() - begin;
+    if let Some(profiler) = PROFILER.lock().as_mut() {
+        profiler.events.push(Event {
+            name: name.into(),
+            cat,
+            start: begin,
+            dur,
+            synthetic: false,
+            kernel: None,
+        });
+    }
+    res
+}
+
+/// Records an eval on `ir`, together with the trace building phase leading up to it
+/// and the compilation and launch of every kernel it ran.
+/// rjit only reports the durations of compilation and execution, so these events are
+/// laid out back to back from the start of the eval and marked as synthetic. The rest
+/// of the eval, mostly waiting for the device, is recorded as a synthetic sync.
+pub fn record_eval(ir: &Trace, f: impl FnOnce()) {
+    let Some(begin) = PROFILER.lock().as_ref().map(|p| p.start.elapsed()) else {
+        return f();
+    };
+    let n_kernels = ir.kernel_history_len();
+    record("eval", "eval", f);
+
+    let kernels = ir.kernel_history_since(n_kernels);
+    let mut guard = PROFILER.lock();
+    let Some(profiler) = guard.as_mut() else {
+        return;
+    };
+    let end = profiler.start.elapsed();
+    profiler.events.push(Event {
+        name: "trace".into(),
+        cat: "trace",
+        start: profiler.last_eval,
+        dur: begin.saturating_sub(profiler.last_eval),
+        synthetic: false,
+        kernel: None,
+    });
+    let mut t = begin;
+    for kernel in &kernels {
+        let name = format!("{:016x}", kernel.hash);
+        let args = Some((kernel.hash, kernel.compile_time, kernel.execution_time));
+        profiler.events.push(Event {
+            name: name.clone(),
+            cat: "compile",
+            start: t,
+            dur: kernel.compile_time,
+            synthetic: true,
+            kernel: args,
+        });
+        t += kernel.compile_time;
+        profiler.events.push(Event {
+            name,
+            cat: "launch",
+            start: t,
+            dur: kernel.execution_time,
+            synthetic: true,
+            kernel: args,
+        });
+        t += kernel.execution_time;
+    }
+    if t < end {
+        profiler.events.push(Event {
+            name: "sync".into(),
+            cat: "sync",
+            start: t,
+            dur: end - t,
+            synthetic: true,
+            kernel: None,
+        });
+    }
+    profiler.last_eval = end;
+}
+
+/// Starts recording a timeline of trace building, kernel compilation, launches and
+/// transfers. Restarts the recording if it is already running.
+#[pyfunction]
+pub fn profile_start() {
+    *PROFILER.lock() = Some(Profiler {
+        start: Instant::now(),
+        last_eval: Duration::ZERO,
+        events: vec![],
+    });
+}
+
+/// Stops recording and writes the timeline as chrome tracing JSON to `path`, which can
+/// be opened in `chrome://tracing` or Perfetto.
+#[pyfunction]
+pub fn profile_stop(path: PathBuf) -> Result<()> {
+    let profiler = PROFILER
+        .lock()
+        .take()
+        .ok_or_else(|| anyhow!("Profiling has not been started!"))?;
+    std::fs::write(path, profiler.to_json())?;
+    Ok(())
+}
+
+/// Context manager recording a timeline to `path`:
+/// `with pyjit.Profile("trace.json"): ...`
+#[pyclass]
+pub struct Profile {
+    path: PathBuf,
+}
+
+#[pymethods]
+impl Profile {
+    #[new]
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+    pub fn __enter__(&self) {
+        profile_start();
+    }
+    pub fn __exit__(
+        &self,
+        _ty: Option<&PyAny>,
+        _value: Option<&PyAny>,
+        _traceback: Option<&PyAny>,
+    ) -> Result<()> {
+        profile_stop(self.path.clone())
+    }
+}
@@ -0,0 +1,489 @@
+//! Recording the computation behind Vars into a portable description, which can
+//! be saved as JSON or bytes and replayed in another process with new inputs.
+use crate::context::{self, ir};
+use crate::host::HostData;
+use crate::interp;
+use crate::io::{self, Reader};
+use crate::var::Var;
+use anyhow::{anyhow, bail, Result};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use rjit::{Op, VarRef, VarType};
+use std::collections::HashMap;
+
+const MAGIC: &[u8; 4] = b"PJTR";
+const VERSION: u32 = 1;
+
+/// Defines `op_name`, the name of a recordable op, and `apply`, which replays an
+/// op by name on the replayed operands `$d`. `$n` is the number of required
+/// operands.
+macro_rules! ops {
+    ($($op:ident $name:literal $n:literal => |$d:ident, $ty:ident| $apply:expr,)*) => {
+        fn op_name(op: &Op) -> Option<&'static str> {
+            match op {
+                $(Op::$op => Some($name),)*
+                _ => None,
+            }
+        }
+        #[allow(unused_variables)]
+        fn apply(name: &str, d: &[VarRef], ty: &VarType) -> Result<VarRef> {
+            match name {
+                $($name => {
+                    if d.len() < $n {
+                        bail!("{} expects {} operands, got {}!", $name, $n, d.len());
+                    }
+                    let ($d, $ty) = (d, ty);
+                    Ok($apply?)
+                })*
+                _ => bail!("Unknown op \"{name}\"!"),
+            }
+        }
+    };
+}
+
+ops! {
+    Add "add" 2 => |d, ty| d[0].add(&d[1]),
+    Sub "sub" 2 => |d, ty| d[0].sub(&d[1]),
+    Mul "mul" 2 => |d, ty| d[0].mul(&d[1]),
+    Div "div" 2 => |d, ty| d[0].div(&d[1]),
+    Mod "mod" 2 => |d, ty| d[0].modulo(&d[1]),
+    Min "min" 2 => |d, ty| d[0].min(&d[1]),
+    Max "max" 2 => |d, ty| d[0].max(&d[1]),
+    And "and" 2 => |d, ty| d[0].and(&d[1]),
+    Or "or" 2 => |d, ty| d[0].or(&d[1]),
+    Xor "xor" 2 => |d, ty| d[0].xor(&d[1]),
+    Shl "shl" 2 => |d, ty| d[0].shl(&d[1]),
+    Shr "shr" 2 => |d, ty| d[0].shr(&d[1]),
+    Eq "eq" 2 => |d, ty| d[0].eq(&d[1]),
+    Neq "neq" 2 => |d, ty| d[0].neq(&d[1]),
+    Lt "lt" 2 => |d, ty| d[0].lt(&d[1]),
+    Le "le" 2 => |d, ty| d[0].le(&d[1]),
+    Gt "gt" 2 => |d, ty| d[0].gt(&d[1]),
+    Ge "ge" 2 => |d, ty| d[0].ge(&d[1]),
+    Fma "fma" 3 => |d, ty| d[0].fma(&d[1], &d[2]),
+    Select "select" 3 => |d, ty| d[0].select(&d[1], &d[2]),
+    Neg "neg" 1 => |d, ty| d[0].neg(),
+    Not "not" 1 => |d, ty| d[0].not(),
+    Abs "abs" 1 => |d, ty| d[0].abs(),
+    Ceil "ceil" 1 => |d, ty| d[0].ceil(),
+    Floor "floor" 1 => |d, ty| d[0].floor(),
+    Trunc "trunc" 1 => |d, ty| d[0].trunc(),
+    Rcp "rcp" 1 => |d, ty| d[0].rcp(),
+    Rsqrt "rsqrt" 1 => |d, ty| d[0].rsqrt(),
+    Sin "sin" 1 => |d, ty| d[0].sin(),
+    Cos "cos" 1 => |d, ty| d[0].cos(),
+    Exp2 "exp2" 1 => |d, ty| d[0].exp2(),
+    Log2 "log2" 1 => |d, ty| d[0].log2(),
+    Popc "popc" 1 => |d, ty| d[0].popc(),
+    Clz "clz" 1 => |d, ty| d[0].clz(),
+    Ctz "ctz" 1 => |d, ty| d[0].ctz(),
+    Cast "cast" 1 => |d, ty| d[0].cast(ty),
+    Bitcast "bitcast" 1 => |d, ty| d[0].bitcast(ty),
+    Gather "gather" 2 => |d, ty| d[0].gather(&d[1], d.get(2)),
+}
+
+fn type_name(ty: &VarType) -> String {
+    format!("{ty:?}").to_lowercase()
+}
+fn parse_type(name: &str) -> Result<VarType> {
+    io::TYPES
+        .iter()
+        .map(|(ty, ..)| ty.clone())
+        .find(|ty| type_name(ty) == name)
+        .ok_or_else(|| anyhow!("Unknown type \"{name}\"!"))
+}
+fn type_code(ty: &VarType) -> u8 {
+    io::TYPES.iter().position(|(t, ..)| t == ty).unwrap() as u8
+}
+
+/// A recorded variable. Inputs have the op `data`, literals `literal` and index
+/// ranges `idx`; all other ops are replayed on the nodes in `deps`.
+#[derive(Clone, Debug, PartialEq)]
+struct Node {
+    op: String,
+    ty: VarType,
+    size: usize,
+    literal: Option<u64>,
+    deps: Vec<usize>,
+}
+
+/// The computation behind a set of Vars, returned by `record`. Buffers and
+/// evaluated variables it depends on become inputs, which `replay` takes in the
+/// order they were first encountered.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recording {
+    /// In dependency order, operands come before the nodes using them.
+    nodes: Vec<Node>,
+    /// Node indices of the inputs.
+    inputs: Vec<usize>,
+    /// Contents of the inputs, if recorded with `data=True`.
+    data: Vec<Option<HostData>>,
+    /// Node indices of the recorded Vars.
+    outputs: Vec<usize>,
+}
+
+impl Recording {
+    pub fn new(vars: &[VarRef], data: bool) -> Result<Self> {
+        Ok(Self::with_inputs(vars, &[], data)?.0)
+    }
+    /// Like `new`, also returning the variables that became inputs, in order.
+    /// Variables in `args` always become inputs, even if they are not evaluated.
+    pub fn with_inputs(
+        vars: &[VarRef],
+        args: &[VarRef],
+        data: bool,
+    ) -> Result<(Self, Vec<VarRef>)> {
+        let mut sources = vec![];
+        let mut rec = Self {
+            nodes: vec![],
+            inputs: vec![],
+            data: vec![],
+            outputs: vec![],
+        };
+        let mut index = HashMap::<usize, usize>::new();
+        for var in vars {
+            // Iterative post-order traversal, as traces can be deeper than the stack.
+            let mut stack = vec![(var.clone(), false)];
+            while let Some((var, ready)) = stack.pop() {
+                if index.contains_key(&var.id()) {
+                    continue;
+                }
+                let op = var.op();
+                let is_input = matches!(op, Op::Data)
+                    || (var.is_evaluated() && !var.is_literal())
+                    || interp::holds(&var)
+                    || args.iter().any(|a| a.id() == var.id());
+                let deps = if is_input { vec![] } else { var.deps() };
+                if !ready && !deps.is_empty() {
+                    stack.push((var.clone(), true));
+                    stack.extend(deps.into_iter().rev().map(|d| (d, false)));
+                    continue;
+                }
+                let name = match op {
+                    _ if is_input => "data",
+                    Op::Literal => "literal",
+                    Op::Idx => "idx",
+                    op => op_name(&op).ok_or_else(|| anyhow!("{op:?} can not be recorded!"))?,
+                };
+                if is_input {
+                    sources.push(var.clone());
+                    rec.inputs.push(rec.nodes.len());
+                    rec.data
+                        .push(data.then(|| HostData::read(&var)).transpose()?);
+                }
+                index.insert(var.id(), rec.nodes.len());
+                rec.nodes.push(Node {
+                    op: name.into(),
+                    ty: var.ty(),
+                    size: var.size(),
+                    literal: if name == "literal" {
+                        var.literal()
+                    } else {
+                        None
+                    },
+                    deps: deps.iter().map(|d| index[&d.id()]).collect(),
+                });
+            }
+            rec.outputs.push(index[&var.id()]);
+        }
+        Ok((rec, sources))
+    }
+
+    /// Rebuilds the recorded Vars in the current context, reading the inputs from
+    /// `inputs`, or from the recorded contents if `inputs` is empty.
+    pub fn replay_with(&self, inputs: &[VarRef]) -> Result<Vec<VarRef>> {
+        let inputs = if inputs.is_empty() && !self.inputs.is_empty() {
+            self.data
+                .iter()
+                .map(|data| {
+                    data.as_ref()
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "Recorded without data, the inputs have to be passed to replay!"
+                            )
+                        })?
+                        .to_var()
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            inputs.to_vec()
+        };
+        if inputs.len() != self.inputs.len() {
+            bail!(
+                "Expected {} inputs, got {}!",
+                self.inputs.len(),
+                inputs.len()
+            );
+        }
+        let mut vars: Vec<VarRef> = Vec::with_capacity(self.nodes.len());
+        let mut inputs = inputs.into_iter().enumerate();
+        for node in &self.nodes {
+            let var = match node.op.as_str() {
+                "data" => {
+                    let (i, var) = inputs
+                        .next()
+                        .ok_or_else(|| anyhow!("More data nodes than inputs!"))?;
+                    if var.ty() != node.ty || var.size() != node.size {
+                        bail!(
+                            "Input {i} has to be of type {:?} and size {}, got {:?} of size {}!",
+                            node.ty,
+                            node.size,
+                            var.ty(),
+                            var.size()
+                        );
+                    }
+                    var
+                }
+                "literal" => {
+                    let bits = node
+                        .literal
+                        .ok_or_else(|| anyhow!("Literal without a value!"))?;
+                    HostData::from_bits(&node.ty, std::iter::once(bits))?.to_literal(node.size)?
+                }
+                "idx" => ir().index(node.size),
+                op => {
+                    let deps = node
+                        .deps
+                        .iter()
+                        .map(|&d| {
+                            vars.get(d)
+                                .cloned()
+                                .ok_or_else(|| anyhow!("Node refers to a later node {d}!"))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    apply(op, &deps, &node.ty)?
+                }
+            };
+            vars.push(var);
+        }
+        self.outputs
+            .iter()
+            .map(|&i| {
+                vars.get(i)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("Output {i} does not exist!"))
+            })
+            .collect()
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        buf.extend(VERSION.to_le_bytes());
+        buf.extend((self.nodes.len() as u32).to_le_bytes());
+        for node in &self.nodes {
+            buf.extend((node.op.len() as u32).to_le_bytes());
+            buf.extend(node.op.as_bytes());
+            buf.push(type_code(&node.ty));
+            buf.extend((node.size as u64).to_le_bytes());
+            buf.push(node.literal.is_some() as u8);
+            buf.extend(node.literal.unwrap_or(0).to_le_bytes());
+            buf.extend((node.deps.len() as u32).to_le_bytes());
+            for dep in &node.deps {
+                buf.extend((*dep as u32).to_le_bytes());
+            }
+        }
+        buf.extend((self.inputs.len() as u32).to_le_bytes());
+        for (input, data) in self.inputs.iter().zip(&self.data) {
+            buf.extend((*input as u32).to_le_bytes());
+            buf.push(data.is_some() as u8);
+            if let Some(data) = data {
+                io::write_data(&mut buf, data);
+            }
+        }
+        buf.extend((self.outputs.len() as u32).to_le_bytes());
+        for output in &self.outputs {
+            buf.extend((*output as u32).to_le_bytes());
+        }
+        buf
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut r = Reader(bytes);
+        if r.take(4)? != MAGIC {
+            bail!("Not a pyjit recording!");
+        }
+        let version = r.u32()?;
+        if version != VERSION {
+            bail!("Unsupported recording version {version}, expected {VERSION}!");
+        }
+        let n_nodes = r.u32()?;
+        let nodes = (0..n_nodes)
+            .map(|_| {
+                let len = r.u32()? as usize;
+                let op = String::from_utf8(r.take(len)?.to_vec())?;
+                let code = r.u8()? as usize;
+                let (ty, ..) = io::TYPES
+                    .get(code)
+                    .ok_or_else(|| anyhow!("Unknown type code {code}!"))?;
+                let size = r.u64()? as usize;
+                let has_literal = r.u8()? != 0;
+                let literal = r.u64()?;
+                let n_deps = r.u32()?;
+                let deps = (0..n_deps)
+                    .map(|_| Ok(r.u32()? as usize))
+                    .collect::<Result<_>>()?;
+                Ok(Node {
+                    op,
+                    ty: ty.clone(),
+                    size,
+                    literal: has_literal.then_some(literal),
+                    deps,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let n_inputs = r.u32()?;
+        let (inputs, data) = (0..n_inputs)
+            .map(|_| {
+                let input = r.u32()? as usize;
+                let data = if r.u8()? != 0 { Some(r.data()?) } else { None };
+                Ok((input, data))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        let n_outputs = r.u32()?;
+        let outputs = (0..n_outputs)
+            .map(|_| Ok(r.u32()? as usize))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            nodes,
+            inputs,
+            data,
+            outputs,
+        })
+    }
+}
+
+#[pymethods]
+impl Recording {
+    /// Number of inputs `replay` expects.
+    #[getter]
+    pub fn n_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+    /// Rebuilds the recorded Vars with new `inputs` in the current context. Without
+    /// inputs, the contents saved with `record(..., data=True)` are used.
+    #[pyo3(signature = (*inputs))]
+    pub fn replay(&self, inputs: &PyTuple) -> PyResult<Vec<Var>> {
+        let inputs = inputs
+            .iter()
+            .map(|v| Ok(v.extract::<Var>()?.0.clone()))
+            .collect::<PyResult<Vec<_>>>()?;
+        let trace = inputs.first().map(|v| v.trace().clone()).unwrap_or_else(ir);
+        let vars = context::with(&trace, || self.replay_with(&inputs))?;
+        Ok(vars.into_iter().map(Var).collect())
+    }
+    pub fn to_json(&self, py: Python) -> PyResult<String> {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let dict = PyDict::new(py);
+                dict.set_item("op", &node.op)?;
+                dict.set_item("ty", type_name(&node.ty))?;
+                dict.set_item("size", node.size)?;
+                if let Some(literal) = node.literal {
+                    dict.set_item("literal", literal)?;
+                }
+                dict.set_item("deps", &node.deps)?;
+                Ok(dict)
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let data = self
+            .data
+            .iter()
+            .map(|data| data.as_ref().map(|d| d.bits()))
+            .collect::<Vec<_>>();
+        let dict = PyDict::new(py);
+        dict.set_item("version", VERSION)?;
+        dict.set_item("nodes", nodes)?;
+        dict.set_item("inputs", &self.inputs)?;
+        dict.set_item("data", data)?;
+        dict.set_item("outputs", &self.outputs)?;
+        py.import("json")?.call_method1("dumps", (dict,))?.extract()
+    }
+    #[staticmethod]
+    pub fn from_json(py: Python, json: &str) -> PyResult<Self> {
+        let dict = py.import("json")?.call_method1("loads", (json,))?;
+        let version = dict.get_item("version")?.extract::<u32>()?;
+        if version != VERSION {
+            return Err(PyErr::new::<PyValueError, _>(format!(
+                "Unsupported recording version {version}, expected {VERSION}!"
+            )));
+        }
+        let nodes = dict
+            .get_item("nodes")?
+            .iter()?
+            .map(|node| {
+                let node = node?;
+                let literal = node.call_method1("get", ("literal",))?;
+                Ok(Node {
+                    op: node.get_item("op")?.extract()?,
+                    ty: parse_type(node.get_item("ty")?.extract()?)?,
+                    size: node.get_item("size")?.extract()?,
+                    literal: literal.extract()?,
+                    deps: node.get_item("deps")?.extract()?,
+                })
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let inputs: Vec<usize> = dict.get_item("inputs")?.extract()?;
+        let data = dict
+            .get_item("data")?
+            .extract::<Vec<Option<Vec<u64>>>>()?
+            .into_iter()
+            .zip(&inputs)
+            .map(|(bits, &input)| {
+                let ty = &nodes
+                    .get(input)
+                    .ok_or_else(|| {
+                        PyErr::new::<PyValueError, _>(format!("Input {input} does not exist!"))
+                    })?
+                    .ty;
+                Ok(bits
+                    .map(|bits| HostData::from_bits(ty, bits.into_iter()))
+                    .transpose()?)
+            })
+            .collect::<PyResult<_>>()?;
+        Ok(Self {
+            nodes,
+            inputs,
+            data,
+            outputs: dict.get_item("outputs")?.extract()?,
+        })
+    }
+    #[pyo3(name = "to_bytes")]
+    pub fn py_to_bytes<'a>(&self, py: Python<'a>) -> &'a PyBytes {
+        PyBytes::new(py, &self.to_bytes())
+    }
+    #[staticmethod]
+    #[pyo3(name = "from_bytes")]
+    pub fn py_from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_bytes(bytes)
+    }
+    pub fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+    pub fn __repr__(&self) -> String {
+        format!(
+            "Recording(nodes={}, inputs={}, outputs={})",
+            self.nodes.len(),
+            self.inputs.len(),
+            self.outputs.len()
+        )
+    }
+}
+
+/// Records the computation behind `vars`. With `data=True` the contents of the
+/// inputs are saved as well, so the recording can be replayed on its own, e.g. to
+/// reproduce a bug.
+#[pyfunction]
+#[pyo3(signature = (*vars, data = false))]
+pub fn record(vars: &PyTuple, data: bool) -> PyResult<Recording> {
+    let vars = vars
+        .iter()
+        .map(|v| Ok(v.extract::<Var>()?.0.clone()))
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(Recording::new(&vars, data)?)
+}
@@ -0,0 +1,62 @@
+use crate::cache;
+use crate::context::ir;
+use pyo3::prelude::*;
+use std::sync::atomic::Ordering;
+
+/// Counters of the current context, accumulated since it was created or
+/// `reset_stats()` was called. Times are in seconds and memory sizes in bytes. Cache hits count kernels found in
+/// the in-memory or the disk cache, misses the kernels that had to be compiled.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct Stats {
+    #[pyo3(get)]
+    pub kernel_launches: usize,
+    #[pyo3(get)]
+    pub cache_hits: u64,
+    #[pyo3(get)]
+    pub cache_misses: u64,
+    #[pyo3(get)]
+    pub compile_time: f64,
+    #[pyo3(get)]
+    pub run_time: f64,
+    #[pyo3(get)]
+    pub allocated_bytes: usize,
+    #[pyo3(get)]
+    pub peak_allocated_bytes: usize,
+    #[pyo3(get)]
+    pub live_variables: usize,
+}
+
+#[pymethods]
+impl Stats {
+    pub fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+#[pyfunction]
+pub fn stats() -> Stats {
+    let ir = ir();
+    let stats = ir.stats();
+    let counters = cache::counters(&ir);
+    let lookups = counters.lookups.load(Ordering::Relaxed);
+    let memory_hits = (stats.kernel_launches as u64).saturating_sub(lookups);
+    Stats {
+        kernel_launches: stats.kernel_launches,
+        cache_hits: memory_hits + counters.hits.load(Ordering::Relaxed),
+        cache_misses: counters.misses.load(Ordering::Relaxed),
+        compile_time: stats.compile_time.as_secs_f64(),
+        run_time: stats.execution_time.as_secs_f64(),
+        allocated_bytes: stats.allocated_bytes,
+        peak_allocated_bytes: stats.peak_allocated_bytes,
+        live_variables: stats.n_variables,
+    }
+}
+
+/// Resets all counters of the current context. The peak memory usage restarts at the currently allocated size.
+#[pyfunction]
+pub fn reset_stats() {
+    let ir = ir();
+    ir.reset_stats();
+    cache::counters(&ir).reset();
+}
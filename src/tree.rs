@@ -0,0 +1,240 @@
+use crate::funcs;
+use crate::var::Var;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyBool, PyDict, PyFloat, PyList, PyLong, PyTuple};
+use rjit::VarType;
+
+/// Whether `obj` is an instance of a dataclass. Checks the type like
+/// `dataclasses.is_dataclass`, without importing `dataclasses` for every leaf.
+fn is_dataclass(obj: &PyAny) -> PyResult<bool> {
+    obj.get_type().hasattr("__dataclass_fields__")
+}
+
+/// Names of the fields of `obj` holding Vars, if it is a dataclass or declares its
+/// fields in a `DRJIT_STRUCT` dict or sequence.
+pub fn fields(py: Python, obj: &PyAny) -> PyResult<Option<Vec<String>>> {
+    if let Ok(fields) = obj.get_type().getattr("DRJIT_STRUCT") {
+        return Ok(Some(
+            fields
+                .iter()?
+                .map(|f| f?.extract::<String>())
+                .collect::<PyResult<_>>()?,
+        ));
+    }
+    if is_dataclass(obj)? {
+        return Ok(Some(
+            py.import("dataclasses")?
+                .call_method1("fields", (obj,))?
+                .iter()?
+                .map(|f| f?.getattr("name")?.extract::<String>())
+                .collect::<PyResult<_>>()?,
+        ));
+    }
+    Ok(None)
+}
+
+/// Creates a copy of `obj` with the fields in `values` replaced.
+/// Dataclasses are rebuilt through `dataclasses.replace`, which also works for frozen
+/// ones; fields excluded from `__init__` are set afterwards. Other structs are
+/// copied, since their constructor may not take the fields.
+fn rebuild(py: Python, obj: &PyAny, values: Vec<(String, PyObject)>) -> PyResult<PyObject> {
+    if !is_dataclass(obj)? {
+        let res = py.import("copy")?.call_method1("copy", (obj,))?;
+        for (field, value) in values {
+            res.setattr(field.as_str(), value)?;
+        }
+        return Ok(res.into());
+    }
+    let dataclasses = py.import("dataclasses")?;
+    let mut init = vec![];
+    for field in dataclasses.call_method1("fields", (obj,))?.iter()? {
+        let field = field?;
+        if field.getattr("init")?.is_true()? {
+            init.push(field.getattr("name")?.extract::<String>()?);
+        }
+    }
+    let (kwargs, rest): (Vec<_>, Vec<_>) = values.into_iter().partition(|(f, _)| init.contains(f));
+    let res = dataclasses.call_method("replace", (obj,), Some(kwargs.into_py_dict(py)))?;
+    let setattr = py
+        .import("builtins")?
+        .getattr("object")?
+        .getattr("__setattr__")?;
+    for (field, value) in rest {
+        setattr.call1((res, field, value))?;
+    }
+    Ok(res.into())
+}
+
+/// Appends all Vars contained in `obj` to `leaves`.
+pub fn leaves(py: Python, obj: &PyAny, leaves: &mut Vec<Var>) -> PyResult<()> {
+    if let Ok(var) = obj.extract::<Var>() {
+        leaves.push(var);
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        for value in dict.values() {
+            self::leaves(py, value, leaves)?;
+        }
+    } else if obj.is_instance_of::<PyList>()? || obj.is_instance_of::<PyTuple>()? {
+        for item in obj.iter()? {
+            self::leaves(py, item?, leaves)?;
+        }
+    } else if let Some(fields) = fields(py, obj)? {
+        for field in fields {
+            self::leaves(py, obj.getattr(field.as_str())?, leaves)?;
+        }
+    }
+    Ok(())
+}
+
+/// Rebuilds the structure of `objs[0]`, replacing every leaf by `f` applied to the
+/// values of all `objs` at that position. Leaves are Vars and everything that is not
+/// a dict, list, tuple or struct. The trees must have the same structure, but may
+/// hold python scalars where the others hold Vars.
+pub fn map(
+    py: Python,
+    objs: &[&PyAny],
+    f: &mut dyn FnMut(&[&PyAny]) -> PyResult<PyObject>,
+) -> PyResult<PyObject> {
+    let obj = objs[0];
+    let children = |key: &dyn Fn(&PyAny) -> PyResult<&PyAny>| {
+        objs.iter().map(|o| key(o)).collect::<PyResult<Vec<_>>>()
+    };
+    if objs.iter().any(|o| o.extract::<Var>().is_ok()) {
+        return f(objs);
+    }
+    if let Ok(dict) = obj.downcast::<PyDict>() {
+        let res = PyDict::new(py);
+        for (key, _) in dict {
+            res.set_item(key, map(py, &children(&|o| o.get_item(key))?, f)?)?;
+        }
+        return Ok(res.into());
+    }
+    if obj.is_instance_of::<PyList>()? || obj.is_instance_of::<PyTuple>()? {
+        for other in &objs[1..] {
+            if other.len()? != obj.len()? {
+                return Err(PyErr::new::<PyValueError, _>(
+                    "Sequences of different lengths can not be combined!",
+                ));
+            }
+        }
+        let items = (0..obj.len()?)
+            .map(|i| map(py, &children(&|o| o.get_item(i))?, f))
+            .collect::<PyResult<Vec<_>>>()?;
+        return Ok(if obj.is_instance_of::<PyList>()? {
+            PyList::new(py, items).into()
+        } else {
+            PyTuple::new(py, items).into()
+        });
+    }
+    if let Some(fields) = fields(py, obj)? {
+        let values = fields
+            .into_iter()
+            .map(|field| {
+                let value = map(py, &children(&|o| o.getattr(field.as_str()))?, f)?;
+                Ok((field, value))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        return rebuild(py, obj, values);
+    }
+    f(objs)
+}
+
+/// Schedules all Vars contained in `objs`.
+#[pyfunction]
+#[pyo3(signature = (*objs))]
+pub fn schedule(py: Python, objs: &PyTuple) -> PyResult<()> {
+    let mut vars = vec![];
+    leaves(py, objs, &mut vars)?;
+    for var in vars {
+        var.0.schedule();
+    }
+    Ok(())
+}
+
+/// The type of the literals python scalars `a` and `b` are converted to by `select`.
+fn scalar_type(a: &PyAny, b: &PyAny) -> PyResult<Option<VarType>> {
+    let is = |check: fn(&PyAny) -> PyResult<bool>| Ok::<_, PyErr>(check(a)? && check(b)?);
+    Ok(if is(|o| o.is_instance_of::<PyBool>())? {
+        Some(VarType::Bool)
+    } else if is(|o| o.is_instance_of::<PyLong>())? {
+        Some(VarType::I32)
+    } else if is(|o| Ok(o.is_instance_of::<PyLong>()? || o.is_instance_of::<PyFloat>()?))? {
+        Some(VarType::F32)
+    } else {
+        None
+    })
+}
+
+/// Blends two trees of the same structure, taking `a` where `mask` is set and `b`
+/// otherwise. Where both trees hold python scalars, they become literals in the
+/// context of `mask`: `bool` for bools, `i32` for ints and `f32` if one of them is a
+/// float. Other leaves have to be equal.
+#[pyfunction]
+pub fn select(py: Python, mask: &PyAny, a: &PyAny, b: &PyAny) -> PyResult<PyObject> {
+    if mask.is_instance_of::<PyBool>()? {
+        return Ok(if mask.is_true()? { a } else { b }.into());
+    }
+    let mask_var = match mask.extract::<Var>() {
+        Ok(var) => var,
+        Err(_) => funcs::bool(mask, None, None)?,
+    };
+    map(py, &[a, b], &mut |leaves| {
+        // Scalars are converted to the type of the Var on the other side.
+        let var = match leaves[0]
+            .extract::<Var>()
+            .or_else(|_| leaves[1].extract::<Var>())
+        {
+            Ok(var) => var,
+            Err(_) => match scalar_type(leaves[0], leaves[1])? {
+                Some(ty) => mask_var.operand(leaves[0], ty)?,
+                None if leaves[0].eq(leaves[1])? => return Ok(leaves[0].into()),
+                None => {
+                    return Err(PyErr::new::<PyTypeError, _>(format!(
+                        "Can not select between {} and {}!",
+                        leaves[0], leaves[1]
+                    )))
+                }
+            },
+        };
+        let a = var.operand(leaves[0], var.0.ty())?;
+        let b = var.operand(leaves[1], var.0.ty())?;
+        let mask = var.operand(mask_var.clone().into_py(py).as_ref(py), VarType::Bool)?;
+        Ok(Var(mask.0.select(&a.0, &b.0)?).into_py(py))
+    })
+}
+
+/// Gathers every Var contained in `obj` at `idx`, keeping other leaves.
+#[pyfunction]
+pub fn gather(py: Python, obj: &PyAny, idx: &PyAny, mask: Option<&PyAny>) -> PyResult<PyObject> {
+    map(py, &[obj], &mut |leaves| match leaves[0].extract::<Var>() {
+        Ok(var) => Ok(var.gather(idx, mask)?.into_py(py)),
+        Err(_) => Ok(leaves[0].into()),
+    })
+}
+
+/// Scatters every Var contained in `obj` into the Var at the same position in `dst`.
+#[pyfunction]
+pub fn scatter(
+    py: Python,
+    obj: &PyAny,
+    dst: &PyAny,
+    idx: &PyAny,
+    mask: Option<&PyAny>,
+) -> PyResult<()> {
+    map(py, &[obj, dst], &mut |leaves| {
+        let dst = match leaves[1].extract::<Var>() {
+            Ok(dst) => dst,
+            // Leaves that are not Vars on either side are left alone.
+            Err(_) if leaves[0].extract::<Var>().is_err() => return Ok(py.None()),
+            Err(_) => {
+                return Err(PyErr::new::<PyTypeError, _>(
+                    "The scatter target has to contain a Var for every Var of the source!",
+                ))
+            }
+        };
+        dst.operand(leaves[0], dst.0.ty())?
+            .scatter(&dst, idx, mask)?;
+        Ok(py.None())
+    })?;
+    Ok(())
+}
@@ -1,6 +1,14 @@
+use crate::bvh;
+use crate::context::{self, Context};
+use crate::dispatch;
+use crate::freeze;
 use crate::funcs::{self, IR};
+use crate::future::{Future, Output};
+use crate::host::HostData;
+use crate::interp;
+use crate::profile;
 use anyhow::Result;
-use pyo3::exceptions::PyTypeError;
+use pyo3::exceptions::{PyTypeError, PyValueError};
 use pyo3::prelude::*;
 use pyo3::types::PyList;
 use rjit::{ReduceOp, VarType};
@@ -33,20 +41,66 @@ impl Var {
     pub fn from_any_of(any: &PyAny, ty: VarType) -> PyResult<Self> {
         match ty {
             VarType::Void => todo!(),
-            VarType::Bool => funcs::bool(any, None),
-            VarType::I8 => funcs::i8(any, None),
-            VarType::U8 => funcs::u8(any, None),
-            VarType::I16 => funcs::i16(any, None),
-            VarType::U16 => funcs::u16(any, None),
-            VarType::I32 => funcs::i32(any, None),
-            VarType::U32 => funcs::u32(any, None),
-            VarType::I64 => funcs::i64(any, None),
-            VarType::U64 => funcs::u64(any, None),
+            VarType::Bool => funcs::bool(any, None, None),
+            VarType::I8 => funcs::i8(any, None, None),
+            VarType::U8 => funcs::u8(any, None, None),
+            VarType::I16 => funcs::i16(any, None, None),
+            VarType::U16 => funcs::u16(any, None, None),
+            VarType::I32 => funcs::i32(any, None, None),
+            VarType::U32 => funcs::u32(any, None, None),
+            VarType::I64 => funcs::i64(any, None, None),
+            VarType::U64 => funcs::u64(any, None, None),
             VarType::F16 => todo!(),
-            VarType::F32 => funcs::f32(any, None),
-            VarType::F64 => funcs::f64(any, None),
+            VarType::F32 => funcs::f32(any, None, None),
+            VarType::F64 => funcs::f64(any, None, None),
         }
     }
+    /// The label of this variable, or its id if it has none, for error messages.
+    pub fn name(&self) -> String {
+        self.0
+            .label()
+            .unwrap_or_else(|| format!("#{}", self.0.id()))
+    }
+    /// Fails if `self` and `other` belong to different contexts.
+    pub fn check_context(&self, other: &Self) -> PyResult<()> {
+        if other.0.trace() != self.0.trace() {
+            return Err(PyErr::new::<PyValueError, _>(format!(
+                "Variables {} and {} belong to different contexts and can not be mixed!",
+                self.name(),
+                other.name()
+            )));
+        }
+        Ok(())
+    }
+    /// Copies this variable to the host, releasing the GIL during the transfer.
+    pub fn read(&self, py: Python) -> Result<HostData> {
+        profile::record("to_host", "transfer", || {
+            py.allow_threads(|| HostData::read(&self.0))
+        })
+    }
+    /// Converts `any` into an operand of type `ty` for an operation on `self`.
+    /// Literals are created in the context of `self`.
+    /// Errors name the variable, keeping their exception type.
+    pub fn operand(&self, any: &PyAny, ty: VarType) -> PyResult<Self> {
+        let py = any.py();
+        let var = context::with(self.0.trace(), || Self::from_any_of(any, ty)).map_err(|err| {
+            PyErr::from_type(
+                err.get_type(py),
+                format!("Invalid operand for {}: {}", self.name(), err.value(py)),
+            )
+        })?;
+        self.check_context(&var)?;
+        Ok(var)
+    }
+}
+
+impl Drop for Var {
+    fn drop(&mut self) {
+        if self.0.ref_count() == 1 {
+            bvh::release(self.0.id());
+        }
+        interp::release(&self.0);
+    }
 }
 
 #[pymethods]
@@ -57,32 +111,71 @@ impl Var {
     pub fn size(&self) -> usize {
         self.0.size()
     }
+    /// The context this variable belongs to.
+    #[getter]
+    pub fn context(&self) -> Context {
+        Context(self.0.trace().clone())
+    }
     pub fn schedule(&self) {
         self.0.schedule()
     }
+    /// Evaluates this variable and its dependencies, but no other scheduled variables.
+    pub fn eval(&self, py: Python) -> PyResult<()> {
+        funcs::eval_vars(py, &[self.0.clone()])
+    }
+
+    #[getter]
+    pub fn id(&self) -> usize {
+        self.0.id()
+    }
+    #[getter]
+    pub fn is_literal(&self) -> bool {
+        self.0.is_literal()
+    }
+    #[getter]
+    pub fn is_evaluated(&self) -> bool {
+        self.0.is_evaluated()
+    }
+    #[getter]
+    pub fn refcount(&self) -> usize {
+        self.0.ref_count()
+    }
+    #[getter]
+    pub fn backend(&self) -> Option<String> {
+        self.0.backend().map(|b| format!("{:?}", b))
+    }
+    /// A name for this variable, shown in kernel comments, error messages and graphs.
+    #[getter]
+    pub fn label(&self) -> Option<String> {
+        self.0.label()
+    }
+    #[setter]
+    pub fn set_label(&self, label: Option<&str>) {
+        self.0.set_label(label)
+    }
 
     pub fn add(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.add(&other.0)?))
     }
     pub fn sub(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.sub(&other.0)?))
     }
     pub fn mul(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.mul(&other.0)?))
     }
     pub fn div(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.div(&other.0)?))
     }
     pub fn modulo(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.modulo(&other.0)?))
     }
     pub fn and(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.and(&other.0)?))
     }
     pub fn rcp(&self) -> PyResult<Self> {
@@ -128,63 +221,63 @@ impl Var {
         Ok(Var(self.0.ctz()?))
     }
     pub fn min(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.min(&other.0)?))
     }
     pub fn max(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.max(&other.0)?))
     }
     pub fn eq(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.eq(&other.0)?))
     }
     pub fn neq(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.neq(&other.0)?))
     }
     pub fn lt(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.lt(&other.0)?))
     }
     pub fn le(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.le(&other.0)?))
     }
     pub fn gt(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.gt(&other.0)?))
     }
     pub fn ge(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.ge(&other.0)?))
     }
 
     pub fn or(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.or(&other.0)?))
     }
     pub fn xor(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.xor(&other.0)?))
     }
     pub fn shl(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.shl(&other.0)?))
     }
     pub fn shr(&self, other: &PyAny) -> PyResult<Self> {
-        let other = Self::from_any_of(other, self.0.ty())?;
+        let other = self.operand(other, self.0.ty())?;
         Ok(Var(self.0.shr(&other.0)?))
     }
 
     pub fn fma(&self, d1: &PyAny, d2: &PyAny) -> PyResult<Self> {
-        let d1 = Self::from_any_of(d1, self.0.ty())?;
-        let d2 = Self::from_any_of(d2, self.0.ty())?;
+        let d1 = self.operand(d1, self.0.ty())?;
+        let d2 = self.operand(d2, self.0.ty())?;
         Ok(Var(self.0.fma(&d1.0, &d2.0)?))
     }
     pub fn select(&self, d1: &PyAny, d2: &PyAny) -> PyResult<Self> {
-        let d1 = Self::from_any_of(d1, self.0.ty())?;
-        let d2 = Self::from_any_of(d2, self.0.ty())?;
+        let d1 = self.operand(d1, self.0.ty())?;
+        let d2 = self.operand(d2, self.0.ty())?;
         Ok(Var(self.0.select(&d1.0, &d2.0)?))
     }
 
@@ -254,7 +347,8 @@ impl Var {
             "f32" => Ok(Self(self.0.bitcast(&VarType::F32)?)),
             "f64" => Ok(Self(self.0.bitcast(&VarType::F64)?)),
             _ => Err(PyErr::new::<PyTypeError, _>(format!(
-                "Type {ty} is not supported!"
+                "Can not bitcast {} to type {ty}, which is not supported!",
+                self.name()
             ))),
         }
     }
@@ -270,8 +364,8 @@ impl Var {
     pub fn tex_lookup(&self, pos: Vec<&PyAny>) -> PyResult<Vec<Self>> {
         let pos = pos
             .iter()
-            .map(|p| Self::from_any_of(p, VarType::F32).unwrap().0)
-            .collect::<Vec<_>>();
+            .map(|p| Ok(self.operand(p, VarType::F32)?.0.clone()))
+            .collect::<PyResult<Vec<_>>>()?;
         let pos_refs = pos.iter().map(|p| p).collect::<Vec<_>>();
         let res = self.0.tex_lookup(pos_refs.as_slice())?;
         let res = res.into_iter().map(|r| Var(r)).collect::<_>();
@@ -282,26 +376,37 @@ impl Var {
         Ok(Var(self.0.compress()?))
     }
 
-    pub fn scatter_reduce(&self, dst: &Self, idx: &PyAny, mask: Option<&PyAny>) -> Result<()> {
-        let mask = mask.map(|m| Self::from_any_of(m, VarType::Bool).unwrap().0);
+    pub fn scatter_reduce(&self, dst: &Self, idx: &PyAny, mask: Option<&PyAny>) -> PyResult<()> {
+        self.check_context(dst)?;
+        let mask = mask
+            .map(|m| self.operand(m, VarType::Bool).map(|m| m.0.clone()))
+            .transpose()?;
+        let mask = dispatch::masked(mask.as_ref())?;
+        freeze::scatter();
         self.0.scatter_reduce(
             &dst.0,
-            &Self::from_any_of(idx, VarType::U32).unwrap().0,
+            &self.operand(idx, VarType::U32)?.0,
             mask.as_ref(),
             ReduceOp::Add,
-        )
+        )?;
+        Ok(())
     }
-    pub fn scatter(&self, dst: &Self, idx: &PyAny, mask: Option<&PyAny>) -> Result<()> {
-        let mask = mask.map(|m| Self::from_any_of(m, VarType::Bool).unwrap().0);
-        self.0.scatter(
-            &dst.0,
-            &Self::from_any_of(idx, VarType::U32).unwrap().0,
-            mask.as_ref(),
-        )
+    pub fn scatter(&self, dst: &Self, idx: &PyAny, mask: Option<&PyAny>) -> PyResult<()> {
+        self.check_context(dst)?;
+        let mask = mask
+            .map(|m| self.operand(m, VarType::Bool).map(|m| m.0.clone()))
+            .transpose()?;
+        let mask = dispatch::masked(mask.as_ref())?;
+        freeze::scatter();
+        self.0
+            .scatter(&dst.0, &self.operand(idx, VarType::U32)?.0, mask.as_ref())?;
+        Ok(())
     }
     pub fn gather(&self, idx: &PyAny, mask: Option<&PyAny>) -> PyResult<Self> {
-        let mask = mask.map(|m| Self::from_any_of(m, VarType::Bool).unwrap().0);
-        let idx = Self::from_any_of(idx, VarType::U32)?.0;
+        let mask = mask
+            .map(|m| self.operand(m, VarType::Bool).map(|m| m.0.clone()))
+            .transpose()?;
+        let idx = self.operand(idx, VarType::U32)?.0.clone();
         Ok(Var(self.0.gather(&idx, mask.as_ref())?))
     }
     pub fn trace_ray(
@@ -320,36 +425,56 @@ impl Var {
         mask: Option<&PyAny>,
     ) -> PyResult<Vec<Self>> {
         let o = [
-            &funcs::f32(o[0], None)?.0,
-            &funcs::f32(o[1], None)?.0,
-            &funcs::f32(o[2], None)?.0,
+            &self.operand(o[0], VarType::F32)?.0,
+            &self.operand(o[1], VarType::F32)?.0,
+            &self.operand(o[2], VarType::F32)?.0,
         ];
         let d = [
-            &funcs::f32(d[0], None)?.0,
-            &funcs::f32(d[1], None)?.0,
-            &funcs::f32(d[2], None)?.0,
+            &self.operand(d[0], VarType::F32)?.0,
+            &self.operand(d[1], VarType::F32)?.0,
+            &self.operand(d[2], VarType::F32)?.0,
         ];
-        let vis_mask = vis_mask.map(|v| funcs::u32(v, None).unwrap().0);
-        let flags = flags.map(|v| funcs::u32(v, None).unwrap().0);
-        let sbt_offset = sbt_offset.map(|v| funcs::u32(v, None).unwrap().0);
-        let sbt_stride = sbt_stride.map(|v| funcs::u32(v, None).unwrap().0);
-        let miss_sbt = miss_sbt.map(|v| funcs::u32(v, None).unwrap().0);
-        let mask = mask.map(|v| funcs::bool(v, None).unwrap().0);
+        let optional = |v: Option<&PyAny>, ty| {
+            v.map(|v| self.operand(v, ty).map(|v| v.0.clone()))
+                .transpose()
+        };
+        let vis_mask = optional(vis_mask, VarType::U32)?;
+        let flags = optional(flags, VarType::U32)?;
+        let sbt_offset = optional(sbt_offset, VarType::U32)?;
+        let sbt_stride = optional(sbt_stride, VarType::U32)?;
+        let miss_sbt = optional(miss_sbt, VarType::U32)?;
+        let mask = optional(mask, VarType::Bool)?;
         let payload = payload
             .into_iter()
-            .map(|v| funcs::u32(v, None).unwrap().0)
-            .collect::<Vec<_>>();
+            .map(|v| Ok(self.operand(v, VarType::U32)?.0.clone()))
+            .collect::<PyResult<Vec<_>>>()?;
         let payload_ref = payload.iter().collect::<Vec<_>>();
 
+        if let Some(bvh) = bvh::get(&self.0) {
+            let tmin = self.operand(tmin, VarType::F32)?.0.clone();
+            let tmax = self.operand(tmax, VarType::F32)?.0.clone();
+            let rays = bvh::Rays {
+                o,
+                d,
+                tmin: &tmin,
+                tmax: &tmax,
+                vis_mask: vis_mask.as_ref(),
+                flags: flags.as_ref(),
+            };
+            let payload = context::with(self.0.trace(), || {
+                bvh::trace_ray(&bvh, &payload, rays, mask.as_ref())
+            })?;
+            return Ok(payload.into_iter().map(Var).collect());
+        }
         Ok(self
             .0
             .trace_ray(
                 &payload_ref,
                 o,
                 d,
-                &funcs::f32(tmin, None)?.0,
-                &funcs::f32(tmax, None)?.0,
-                &funcs::f32(t, None)?.0,
+                &self.operand(tmin, VarType::F32)?.0,
+                &self.operand(tmax, VarType::F32)?.0,
+                &self.operand(t, VarType::F32)?.0,
                 vis_mask.as_ref(),
                 flags.as_ref(),
                 sbt_offset.as_ref(),
@@ -361,57 +486,50 @@ impl Var {
             .map(|p| Var(p))
             .collect::<Vec<_>>())
     }
-    pub fn __repr__(&self) -> Result<String> {
-        self.schedule();
-        funcs::eval();
-        Ok(match self.0.ty() {
-            VarType::Void => format!(""),
-            VarType::Bool => format!("bool{:?}", self.0.to_host::<bool>()?.as_slice()),
-            VarType::I8 => format!("i8{:?}", self.0.to_host::<i8>()?.as_slice()),
-            VarType::U8 => format!("u8{:?}", self.0.to_host::<u8>()?.as_slice()),
-            VarType::I16 => format!("i16{:?}", self.0.to_host::<i16>()?.as_slice()),
-            VarType::U16 => format!("u16{:?}", self.0.to_host::<u16>()?.as_slice()),
-            VarType::I32 => format!("i32{:?}", self.0.to_host::<i32>()?.as_slice()),
-            VarType::U32 => format!("u32{:?}", self.0.to_host::<u32>()?.as_slice()),
-            VarType::I64 => format!("i64{:?}", self.0.to_host::<i64>()?.as_slice()),
-            VarType::U64 => format!("u64{:?}", self.0.to_host::<u64>()?.as_slice()),
-            VarType::F16 => todo!(),
-            VarType::F32 => format!("f32{:?}", self.0.to_host::<f32>()?.as_slice()),
-            VarType::F64 => format!("f64{:?}", self.0.to_host::<f64>()?.as_slice()),
-        })
+    pub fn __repr__(&self, py: Python) -> Result<String> {
+        self.eval(py)?;
+        if self.0.ty() == VarType::Void {
+            return Ok(String::new());
+        }
+        Ok(self.read(py)?.to_string())
     }
     pub fn to_list<'a>(&self, py: Python<'a>) -> Result<&'a PyList> {
-        Ok(match self.0.ty() {
-            VarType::Void => todo!(),
-            VarType::Bool => PyList::new(py, self.0.to_host::<bool>()?),
-            VarType::I8 => PyList::new(py, self.0.to_host::<i8>()?),
-            VarType::U8 => PyList::new(py, self.0.to_host::<u8>()?),
-            VarType::I16 => PyList::new(py, self.0.to_host::<i16>()?),
-            VarType::U16 => PyList::new(py, self.0.to_host::<u16>()?),
-            VarType::I32 => PyList::new(py, self.0.to_host::<i32>()?),
-            VarType::U32 => PyList::new(py, self.0.to_host::<u32>()?),
-            VarType::I64 => PyList::new(py, self.0.to_host::<i64>()?),
-            VarType::U64 => PyList::new(py, self.0.to_host::<u64>()?),
-            VarType::F16 => todo!(),
-            VarType::F32 => PyList::new(py, self.0.to_host::<f32>()?),
-            VarType::F64 => PyList::new(py, self.0.to_host::<f64>()?),
-        })
+        Ok(self.read(py)?.into_list(py))
     }
     pub fn to_numpy<'a>(&self, py: Python<'a>) -> Result<&'a PyAny> {
-        Ok(match self.0.ty() {
-            VarType::Void => todo!(),
-            VarType::Bool => numpy::PyArray1::<bool>::from_vec(py, self.0.to_host::<bool>()?),
-            VarType::I8 => numpy::PyArray1::<i8>::from_vec(py, self.0.to_host::<i8>()?),
-            VarType::U8 => numpy::PyArray1::<u8>::from_vec(py, self.0.to_host::<u8>()?),
-            VarType::I16 => numpy::PyArray1::<i16>::from_vec(py, self.0.to_host::<i16>()?),
-            VarType::U16 => numpy::PyArray1::<u16>::from_vec(py, self.0.to_host::<u16>()?),
-            VarType::I32 => numpy::PyArray1::<i32>::from_vec(py, self.0.to_host::<i32>()?),
-            VarType::U32 => numpy::PyArray1::<u32>::from_vec(py, self.0.to_host::<u32>()?),
-            VarType::I64 => numpy::PyArray1::<i64>::from_vec(py, self.0.to_host::<i64>()?),
-            VarType::U64 => numpy::PyArray1::<u64>::from_vec(py, self.0.to_host::<u64>()?),
-            VarType::F16 => todo!(),
-            VarType::F32 => numpy::PyArray1::<f32>::from_vec(py, self.0.to_host::<f32>()?),
-            VarType::F64 => numpy::PyArray1::<f64>::from_vec(py, self.0.to_host::<f64>()?),
+        Ok(self.read(py)?.into_numpy(py).into_ref(py))
+    }
+    /// Evaluates this variable and copies it to the host on a background thread.
+    /// `wait()` on the returned future gives the numpy array.
+    pub fn to_numpy_async(&self) -> Future {
+        let ir = self.0.trace().clone();
+        let var = self.0.clone();
+        Future::spawn(move || {
+            funcs::eval_on(&ir, &[var.clone()])?;
+            Ok(Output::Host(HostData::read(&var)?))
+        })
+    }
+
+    /// Pickles the type and contents, evaluating the variable if needed. Unpickling
+    /// creates a new variable in the context that is current at that point.
+    pub fn __reduce__(&self, py: Python) -> PyResult<(PyObject, (PyObject,))> {
+        let ty = format!("{:?}", self.0.ty()).to_lowercase();
+        let init = py.import("pyjit")?.getattr(ty.as_str())?;
+        self.eval(py)?;
+        Ok((init.into(), (self.read(py)?.into_numpy(py),)))
+    }
+    /// Returns a Var referring to the same variable, so scatters into one are
+    /// visible through the other.
+    pub fn __copy__(&self) -> Self {
+        self.clone()
+    }
+    /// Evaluates the variable and copies its contents into a new variable of the
+    /// same context, independent of the original.
+    pub fn __deepcopy__(&self, py: Python, _memo: &PyAny) -> PyResult<Self> {
+        self.eval(py)?;
+        let data = self.read(py)?.into_numpy(py);
+        context::with(self.0.trace(), || {
+            Self::from_any_of(data.as_ref(py), self.0.ty())
         })
     }
 }
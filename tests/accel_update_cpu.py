@@ -0,0 +1,65 @@
+import pyjit
+
+# Moves a triangle and an instance of it, then traces the refit acceleration structure.
+pyjit.set_backend("cpu")
+
+identity = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+
+desc = pyjit.AccelDesc()
+t0 = desc.add_triangles(
+    pyjit.f32([1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0]), pyjit.u32([0, 1, 2])
+)
+desc.add_miss_group("__miss__ms", "")
+desc.add_hit_group("__closesthit__ch", "")
+i0 = desc.add_instance(t0, identity, 0)
+
+accel = pyjit.Accel(desc)
+
+
+def trace():
+    payload = accel.trace_ray([0, 0, 0], [0.6, 0.6, 0.0], [0.0, 0.0, 1.0], 0.001, 1000.0, 0.0)
+    return pyjit.bool(payload[0]), payload[2]
+
+
+print(f"{trace()=}")
+assert trace()[1].to_list() == [0]
+
+# Move the triangle away from the ray, only its geometry is refit.
+accel.update_vertices(t0, pyjit.f32([3.0, 0.0, 1.0, 2.0, 1.0, 1.0, 3.0, 1.0, 1.0]))
+accel.refit()
+print(f"{trace()=}")
+assert trace()[0].to_list() == [False]
+
+# Move it back with a second instance, the top level is rebuilt.
+i1 = accel.add_instance(t0, [1.0, 0.0, 0.0, -2.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0], 0, instance_id=7)
+accel.rebuild()
+print(f"{trace()=}")
+assert trace()[1].to_list() == [7]
+
+accel.remove_instance(i1)
+accel.rebuild()
+print(f"{trace()=}")
+assert trace()[0].to_list() == [False]
+
+# Move the remaining instance back onto the ray, only the top level is refit.
+accel.set_transform(i0, [1.0, 0.0, 0.0, -2.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0])
+accel.refit()
+print(f"{trace()=}")
+assert trace()[0].to_list() == [True]
+assert trace()[1].to_list() == [0]
+
+# Replace it by an instance with another id. Adding and removing an instance keeps
+# their number, but the top level is rebuilt even when refitting.
+i2 = accel.add_instance(t0, [1.0, 0.0, 0.0, -2.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0], 0, instance_id=9)
+accel.remove_instance(i0)
+accel.refit()
+print(f"{trace()=}")
+assert trace()[1].to_list() == [9]
+
+# Removed instances keep their index, but can not be moved anymore.
+try:
+    accel.set_transform(i1, identity)
+except IndexError:
+    pass
+else:
+    raise AssertionError("set_transform accepted a removed instance")
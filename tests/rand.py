@@ -26,6 +26,8 @@ PCG32_MULT = 0x5851F42D4C957F2D
 
 
 class PCG32:
+    DRJIT_STRUCT = {"state": pyjit.Var, "inc": pyjit.Var}
+
     def __init__(
         self, size=1, initstate=PCG32_DEFAULT_STATE, initseq=PCG32_DEFAULT_STREAM
     ):
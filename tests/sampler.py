@@ -67,8 +67,7 @@ class Independent(Sampler):
         self.rng = PCG32(1, v0, v1)
 
     def schedule(self):
-        self.rng.inc.schedule()
-        self.rng.state.schedule()
+        pyjit.schedule(self.rng)
 
     def next_1d(self):
         return self.rng.next_f32()
@@ -0,0 +1,85 @@
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+def cpu_context():
+    ctx = pyjit.Context()
+    assert ctx.set_backend("cpu") == "cpu"
+    return ctx
+
+
+def test_available_backends():
+    available = pyjit.available_backends()
+    assert available[-1] == "cpu"
+    assert available == [b for b in ["optix", "cuda", "cpu"] if b in available]
+    if "optix" in available:
+        assert "cuda" in available
+
+
+def test_fallback_order():
+    available = pyjit.available_backends()
+    ctx = pyjit.Context()
+    assert ctx.set_backend(["optix", "cuda", "cpu"]) == available[0]
+    with ctx:
+        assert pyjit.backend() == available[0]
+    ctx = pyjit.Context()
+    expected = "cuda" if "cuda" in available else "cpu"
+    assert ctx.set_backend(["cuda", "cpu"]) == expected
+    with ctx:
+        assert pyjit.backend() == expected
+
+
+def test_unknown_backend_raises():
+    ctx = pyjit.Context()
+    cases = [("vulkan", ValueError), (["cuda", "vulkan"], ValueError), (3, TypeError)]
+    for backend, error in cases:
+        try:
+            ctx.set_backend(backend)
+        except error:
+            pass
+        else:
+            raise AssertionError(f"set_backend({backend!r}) was accepted")
+
+
+def test_failed_switch_keeps_cpu():
+    if "cuda" in pyjit.available_backends():
+        return
+    ctx = cpu_context()
+    try:
+        ctx.set_backend("cuda")
+    except RuntimeError:
+        pass
+    else:
+        raise AssertionError("switched to cuda without a device")
+    with ctx:
+        assert pyjit.backend() == "cpu"
+        assert (pyjit.f32([1.0, 2.0]) * 2.0).to_list() == [2.0, 4.0]
+
+
+def test_refuses_to_switch_with_live_variables():
+    if "cuda" not in pyjit.available_backends():
+        return
+    ctx = cpu_context()
+    with ctx:
+        x = pyjit.f32([1.0, 2.0])
+    try:
+        ctx.set_backend("cuda")
+    except RuntimeError as err:
+        assert "variables of the cpu backend" in str(err)
+    else:
+        raise AssertionError("switched away from cpu with live variables")
+    assert ctx.set_backend(["cuda", "cpu"]) == "cpu"
+    assert (x * 2.0).to_list() == [2.0, 4.0]
+    del x
+    assert ctx.set_backend("cuda") == "cuda"
+    with ctx:
+        assert pyjit.backend() == "cuda"
+
+
+if __name__ == "__main__":
+    test_available_backends()
+    test_fallback_order()
+    test_unknown_backend_raises()
+    test_failed_switch_keeps_cpu()
+    test_refuses_to_switch_with_live_variables()
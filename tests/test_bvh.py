@@ -0,0 +1,103 @@
+import pyjit
+
+pyjit.set_backend("cpu")
+
+IDENTITY = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+
+
+def triangle_accel(**instance):
+    desc = pyjit.AccelDesc()
+    vertices = pyjit.f32([1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0])
+    t0 = desc.add_triangles(vertices, pyjit.u32([0, 1, 2]))
+    desc.add_instance(t0, IDENTITY, 0, **instance)
+    desc.add_miss_group("__miss__ms", "")
+    desc.add_hit_group("__closesthit__ch", "")
+    return pyjit.accel(desc)
+
+
+def trace(accel, o, d, **kwargs):
+    payload = accel.trace_ray([0, 0, 0, 0, 0], o, d, 0.001, 1000.0, 0.0, **kwargs)
+    return payload[0].to_list()
+
+
+def test_hit_and_miss():
+    accel = triangle_accel()
+    assert trace(accel, [[0.6, 0.1], 0.6, 0.0], [0.0, 0.0, 1.0]) == [1, 0]
+
+
+# Seen from below, the test triangle faces the rays along +z.
+O = [0.6, 0.6, 0.0]
+D = [0.0, 0.0, 1.0]
+CULL_BACK_FACING = 1 << 4
+CULL_FRONT_FACING = 1 << 5
+
+
+def test_visibility_mask():
+    accel = triangle_accel(visibility_mask=0b01)
+    assert trace(accel, O, D) == [1]
+    assert trace(accel, O, D, vis_mask=0b01) == [1]
+    assert trace(accel, O, D, vis_mask=0b11) == [1]
+    assert trace(accel, O, D, vis_mask=0b10) == [0]
+    assert trace(accel, O, D, vis_mask=[0b01, 0b10, 0b100]) == [1, 0, 0]
+
+
+def test_culling():
+    accel = triangle_accel()
+    assert trace(accel, O, D, flags=CULL_FRONT_FACING) == [0]
+    assert trace(accel, O, D, flags=CULL_BACK_FACING) == [1]
+
+
+def test_flip_winding():
+    accel = triangle_accel(flags=["flip_winding"])
+    assert trace(accel, O, D, flags=CULL_FRONT_FACING) == [1]
+    assert trace(accel, O, D, flags=CULL_BACK_FACING) == [0]
+
+
+def test_disable_culling():
+    for flags in (["disable_culling"], ["disable_culling", "flip_winding"]):
+        accel = triangle_accel(flags=flags)
+        assert trace(accel, O, D, flags=CULL_FRONT_FACING) == [1]
+        assert trace(accel, O, D, flags=CULL_BACK_FACING) == [1]
+
+
+def test_payload():
+    # Two triangles of one geometry, splitting the unit square at z = 1.
+    desc = pyjit.AccelDesc()
+    vertices = pyjit.f32([1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 1.0])
+    t0 = desc.add_triangles(vertices, pyjit.u32([0, 1, 2, 0, 1, 3]))
+    desc.add_instance(t0, IDENTITY, 0, instance_id=7)
+    desc.add_miss_group("__miss__ms", "")
+    desc.add_hit_group("__closesthit__ch", "")
+    accel = pyjit.accel(desc)
+    payload = accel.trace_ray(
+        [0, 9, 9, 9, 9], [[0.6, 0.2, 2.0], [0.6, 0.2, 2.0], 0.0], D, 0.001, 1000.0, 0.0
+    )
+    assert payload[0].to_list() == [1, 1, 0]
+    assert payload[1].to_list() == [0, 1, 9]
+    assert payload[2].to_list() == [7, 7, 9]
+    # `u` and `v` weight the second and third vertex of the hit triangle.
+    u = payload[3].bitcast("f32").to_list()
+    v = payload[4].bitcast("f32").to_list()
+    for got, expected in zip(u[:2] + v[:2], [0.4, 0.2, 0.2, 0.6]):
+        assert abs(got - expected) < 1e-6
+    assert payload[3].to_list()[2] == 9 and payload[4].to_list()[2] == 9
+
+
+def test_mismatched_sizes_raise():
+    accel = triangle_accel()
+    try:
+        trace(accel, [[0.6, 0.1], [0.6, 0.6, 0.6], 0.0], [0.0, 0.0, 1.0])
+    except RuntimeError as err:
+        assert "o.x" in str(err)
+    else:
+        raise AssertionError("mismatched operand sizes were accepted")
+
+
+if __name__ == "__main__":
+    test_hit_and_miss()
+    test_visibility_mask()
+    test_culling()
+    test_flip_winding()
+    test_disable_culling()
+    test_payload()
+    test_mismatched_sizes_raise()
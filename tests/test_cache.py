@@ -0,0 +1,110 @@
+import os
+import tempfile
+
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+def with_cache_dir(f, max_size=None):
+    old = pyjit.cache_dir()
+    with tempfile.TemporaryDirectory() as dir:
+        pyjit.set_cache_dir(dir, max_size)
+        try:
+            f(pyjit.cache_dir())
+        finally:
+            pyjit.set_cache_dir(None if old is None else os.path.dirname(old))
+
+
+def entries(dir):
+    return sorted(name for name in os.listdir(dir) if name.endswith(".bin"))
+
+
+def cuda_context():
+    ctx = pyjit.Context()
+    ctx.set_backend("cuda")
+    return ctx
+
+
+def test_set_cache_dir():
+    def check(dir):
+        assert os.path.isdir(dir)
+        assert os.path.basename(dir) == "v1"
+
+    with_cache_dir(check)
+    old = pyjit.cache_dir()
+    pyjit.set_cache_dir(None)
+    try:
+        assert pyjit.cache_dir() is None
+    finally:
+        pyjit.set_cache_dir(None if old is None else os.path.dirname(old))
+
+
+def test_clear_cache_keeps_temporary_files():
+    def check(dir):
+        for name in ("0000000000000001.bin", "0000000000000002.bin", "0000000000000003.tmp42"):
+            open(os.path.join(dir, name), "wb").close()
+        pyjit.clear_cache()
+        assert sorted(os.listdir(dir)) == ["0000000000000003.tmp42"]
+
+    with_cache_dir(check)
+
+
+def test_second_run_loads_from_disk():
+    if "cuda" not in pyjit.available_backends():
+        return
+
+    def run():
+        with cuda_context():
+            pyjit.reset_stats()
+            pyjit.eval(pyjit.f32([1.0, 2.0]) * 3.0 + 1.0)
+            return pyjit.stats()
+
+    def check(dir):
+        first = run()
+        assert first.cache_misses >= 1
+        assert len(entries(dir)) == first.cache_misses
+        # A new context has an empty in-memory cache, the kernel comes from disk.
+        second = run()
+        assert second.cache_misses == 0
+        assert second.cache_hits >= first.cache_misses
+
+    with_cache_dir(check)
+
+
+def test_existing_contexts_use_new_cache_dir():
+    if "cuda" not in pyjit.available_backends():
+        return
+    ctx = cuda_context()
+
+    def check(dir):
+        with ctx:
+            pyjit.eval(pyjit.f32([1.0, 2.0]) * 5.0)
+        assert len(entries(dir)) >= 1
+
+    with_cache_dir(check)
+
+
+def test_eviction_keeps_newest_entry():
+    if "cuda" not in pyjit.available_backends():
+        return
+
+    def check(dir):
+        with cuda_context():
+            pyjit.eval(pyjit.f32([1.0, 2.0]) * 7.0)
+            first = entries(dir)
+            pyjit.eval(pyjit.f32([1.0, 2.0]) - 7.0)
+        remaining = entries(dir)
+        assert len(remaining) == 1 and remaining != first
+        pyjit.clear_cache()
+        assert entries(dir) == []
+
+    with_cache_dir(check, max_size=1)
+
+
+if __name__ == "__main__":
+    test_set_cache_dir()
+    test_clear_cache_keeps_temporary_files()
+    test_second_run_loads_from_disk()
+    test_existing_contexts_use_new_cache_dir()
+    test_eviction_keeps_newest_entry()
@@ -0,0 +1,96 @@
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+def test_enter_returns_context():
+    ctx = pyjit.Context()
+    ctx.set_backend("cpu")
+    with ctx as entered:
+        assert entered == ctx
+        assert pyjit.Context.current() == ctx
+
+
+def test_initializer_rejects_other_context():
+    x = pyjit.f32([1.0, 2.0])
+    ctx = pyjit.Context()
+    ctx.set_backend("cpu")
+    with ctx:
+        try:
+            pyjit.f32(x)
+        except ValueError:
+            pass
+        else:
+            raise AssertionError("mixing contexts was not detected")
+
+
+def test_trace_ray_operand_errors_raise():
+    x = pyjit.f32([1.0])
+    try:
+        x.trace_ray([0], [0.0] * 3, [0.0] * 3, 0.0, 1.0, 0.0, vis_mask="bad")
+    except TypeError:
+        pass
+    else:
+        raise AssertionError("invalid operand was accepted")
+
+
+def test_explicit_context():
+    ctx = pyjit.Context()
+    ctx.set_backend("cpu")
+    x = pyjit.f32([1.0, 2.0], ctx=ctx)
+    y = pyjit.u32(3, 2, ctx=ctx)
+    assert x.context() == ctx and y.context() == ctx
+    assert pyjit.Context.current() != ctx
+    assert pyjit.f32(x, ctx=ctx).context() == ctx
+    try:
+        pyjit.f32(x)
+    except ValueError:
+        pass
+    else:
+        raise AssertionError("mixing contexts was not detected")
+
+
+def test_explicit_context_accel():
+    ctx = pyjit.Context()
+    ctx.set_backend("cpu")
+    desc = pyjit.AccelDesc()
+    vertices = pyjit.f32([1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0], ctx=ctx)
+    t0 = desc.add_triangles(vertices, pyjit.u32([0, 1, 2], ctx=ctx))
+    desc.add_instance(t0, [1.0, 0, 0, 0, 0, 1.0, 0, 0, 0, 0, 1.0, 0], 0)
+    desc.add_miss_group("__miss__ms", "")
+    desc.add_hit_group("__closesthit__ch", "")
+    assert pyjit.accel(desc, ctx=ctx).context() == ctx
+    accel = pyjit.Accel(desc, ctx=ctx)
+    assert accel.var.context() == ctx
+    accel.update_vertices(0, [1.0, 0.0, 2.0, 0.0, 1.0, 2.0, 1.0, 1.0, 2.0])
+    accel.refit()
+    assert accel.var.context() == ctx
+
+
+def test_explicit_context_texture():
+    if "cuda" not in pyjit.available_backends():
+        return
+    ctx = pyjit.Context()
+    ctx.set_backend("cuda")
+    tex = pyjit.texture([4, 4], 1, ctx=ctx)
+    assert tex.context() == ctx
+
+
+def test_tex_lookup_operand_errors_raise():
+    x = pyjit.f32([1.0])
+    try:
+        x.tex_lookup(["bad", 0.5])
+    except TypeError:
+        pass
+    else:
+        raise AssertionError("invalid operand was accepted")
+
+
+if __name__ == "__main__":
+    test_enter_returns_context()
+    test_initializer_rejects_other_context()
+    test_trace_ray_operand_errors_raise()
+    test_explicit_context()
+    test_explicit_context_accel()
+    test_explicit_context_texture()
+    test_tex_lookup_operand_errors_raise()
@@ -0,0 +1,89 @@
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+def cuda_context():
+    ctx = pyjit.Context()
+    ctx.set_backend("cuda")
+    return ctx
+
+
+def test_cpu_backend_has_no_kernels():
+    x = pyjit.f32([1.0, 2.0]) * 2.0
+    pyjit.eval(x)
+    assert pyjit.kernel_history() == []
+    try:
+        pyjit.kernel_source(x + 1.0)
+    except RuntimeError as err:
+        assert "generates no kernels" in str(err)
+    else:
+        raise AssertionError("kernel_source worked on the cpu backend")
+
+
+def test_kernel_source_launches_nothing():
+    if "cuda" not in pyjit.available_backends():
+        return
+    with cuda_context():
+        y = pyjit.f32([1.0, 2.0]) * 3.0
+        source = pyjit.kernel_source(y)
+        assert isinstance(source, str) and source
+        assert pyjit.kernel_history() == []
+        pyjit.eval(y)
+        history = pyjit.kernel_history()
+        assert len(history) == 1
+        assert history[0].source == source
+        assert history[0].size == 2
+
+
+def test_kernel_source_keeps_scheduled_variables():
+    if "cuda" not in pyjit.available_backends():
+        return
+    with cuda_context():
+        a = pyjit.f32([1.0, 2.0]) + 1.0
+        b = pyjit.f32([1.0, 2.0]) - 1.0
+        pyjit.schedule(a)
+        pyjit.kernel_source(b)
+        pyjit.eval()
+        assert a.is_evaluated
+        assert not b.is_evaluated
+
+
+def node(dot, id):
+    lines = [line for line in dot.splitlines() if line.strip().startswith(f"{id} [label=")]
+    assert len(lines) == 1, dot
+    return lines[0]
+
+
+def test_graphviz():
+    a = pyjit.f32([1.0, 2.0])
+    a.label = "radiance"
+    b = a * 2.0
+    dot = pyjit.graphviz(b, labels=["{scaled|b}"])
+    assert dot.startswith("digraph {\n    rankdir=BT;\n")
+    assert dot.endswith("}\n")
+
+    # Labels are escaped, nodes show their id, op, type and size.
+    assert f'label="{{\\{{scaled\\|b\\}}|#{b.id}|Mul|F32[2]}}" fillcolor=white' in node(dot, b.id)
+    assert f'label="{{radiance|#{a.id}|Data|F32[2]|evaluated}}" fillcolor=lightblue' in node(
+        dot, a.id
+    )
+    (literal,) = [
+        line.split()[0]
+        for line in dot.splitlines()
+        if "|literal}" in line and "fillcolor=lightgrey" in line
+    ]
+    assert f"#{literal}|Literal|F32[1]|literal" in node(dot, literal)
+
+    # Edges point from the operands to their users, labeled with the operand index.
+    edges = [line.strip() for line in dot.splitlines() if "->" in line]
+    assert sorted(edges) == sorted(
+        [f'{a.id} -> {b.id} [label="0"];', f'{literal} -> {b.id} [label="1"];']
+    )
+
+
+if __name__ == "__main__":
+    test_cpu_backend_has_no_kernels()
+    test_kernel_source_launches_nothing()
+    test_kernel_source_keeps_scheduled_variables()
+    test_graphviz()
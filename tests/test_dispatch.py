@@ -0,0 +1,73 @@
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+def test_switch_selects_results():
+    index = pyjit.u32([0, 1, 1, 0])
+    x = pyjit.f32([1.0, 2.0, 3.0, 4.0])
+    res = pyjit.switch(index, [lambda x: x + 1.0, lambda x: x * 10.0], x)
+    assert res.to_list() == [2.0, 20.0, 30.0, 5.0]
+
+
+def test_switch_masks_scatters():
+    index = pyjit.u32([0, 1, 1, 0])
+    counts = pyjit.u32([0, 0])
+
+    def count(slot):
+        def f():
+            pyjit.u32(1).scatter_reduce(counts, slot)
+
+        return f
+
+    pyjit.switch(index, [count(0), count(1)])
+    pyjit.eval(counts)
+    # Each callee only writes the lanes that select it.
+    assert counts.to_list() == [2, 2]
+
+
+def test_switch_out_of_range_is_zero():
+    index = pyjit.u32([0, 5, 1])
+    x = pyjit.f32([1.0, 2.0, 3.0])
+    fns = [lambda x: (x + 1.0, x < 0.0), lambda x: (x * 10.0, x > 0.0)]
+    res = pyjit.switch(index, fns, x)
+    assert res[0].to_list() == [2.0, 0.0, 30.0]
+    assert res[1].to_list() == [False, False, True]
+
+
+class Scale:
+    def __init__(self, factor):
+        self.factor = factor
+
+    def apply(self, x):
+        return x * self.factor
+
+
+def test_dispatch_calls_registered_instances():
+    a = pyjit.register(Scale(2.0))
+    b = pyjit.register(Scale(3.0))
+    try:
+        ptr = pyjit.u32([b, a, 0, b])
+        res = pyjit.dispatch(ptr, "apply", pyjit.f32([1.0, 2.0, 3.0, 4.0]))
+        # Id 0 is the null pointer and selects no instance.
+        assert res.to_list() == [3.0, 4.0, 0.0, 12.0]
+    finally:
+        pyjit.unregister(a)
+        pyjit.unregister(b)
+
+
+def test_unregister_unknown_id():
+    try:
+        pyjit.unregister(1000)
+    except ValueError as err:
+        assert "No instance with id 1000" in str(err)
+    else:
+        raise AssertionError("unregistering an unknown id succeeded")
+
+
+if __name__ == "__main__":
+    test_switch_selects_results()
+    test_switch_masks_scatters()
+    test_switch_out_of_range_is_zero()
+    test_dispatch_calls_registered_instances()
+    test_unregister_unknown_id()
@@ -0,0 +1,163 @@
+import pyjit
+
+pyjit.set_backend("cpu")
+
+calls = 0
+offset = pyjit.f32([1.0, 1.0, 1.0])
+
+
+@pyjit.freeze
+def shift(x):
+    global calls
+    calls += 1
+    return x + offset
+
+
+def test_cache_hit_skips_function():
+    global calls
+    shift.clear()
+    calls = 0
+    assert shift(pyjit.f32([1.0, 2.0, 3.0])).to_list() == [2.0, 3.0, 4.0]
+    assert shift(pyjit.f32([4.0, 5.0, 6.0])).to_list() == [5.0, 6.0, 7.0]
+    assert calls == 1
+    assert shift.n_recordings == 1
+
+
+def test_layout_change_records_again():
+    global calls
+    shift.clear()
+    calls = 0
+    shift(pyjit.f32([1.0, 2.0, 3.0]))
+    assert shift(pyjit.f32([1.0])).to_list() == [2.0, 2.0, 2.0]
+    assert calls == 2
+    assert shift.n_recordings == 2
+
+
+def test_rebound_global_is_replayed():
+    global calls, offset
+    shift.clear()
+    calls = 0
+    shift(pyjit.f32([1.0, 2.0, 3.0]))
+    offset = pyjit.f32([10.0, 20.0, 30.0])
+    try:
+        assert shift(pyjit.f32([1.0, 2.0, 3.0])).to_list() == [11.0, 22.0, 33.0]
+        assert calls == 1
+        # A captured Var of another size needs a new recording.
+        offset = pyjit.f32([5.0])
+        assert shift(pyjit.f32([1.0, 2.0, 3.0])).to_list() == [6.0, 7.0, 8.0]
+        assert calls == 2
+    finally:
+        offset = pyjit.f32([1.0, 1.0, 1.0])
+
+
+def test_rebound_closure_variable_is_replayed():
+    scale = pyjit.f32([2.0, 2.0])
+
+    @pyjit.freeze
+    def f(x):
+        return x * scale
+
+    assert f(pyjit.f32([1.0, 2.0])).to_list() == [2.0, 4.0]
+    scale = pyjit.f32([3.0, 3.0])
+    assert f(pyjit.f32([1.0, 2.0])).to_list() == [3.0, 6.0]
+    assert f.n_recordings == 1
+
+
+def test_scalars_are_part_of_the_layout():
+    @pyjit.freeze
+    def f(x, n):
+        return x * float(n)
+
+    x = pyjit.f32([1.0, 2.0])
+    assert f(x, 2).to_list() == [2.0, 4.0]
+    assert f(x, 3).to_list() == [3.0, 6.0]
+    assert f.n_recordings == 2
+
+
+def test_unsupported_arguments_are_rejected():
+    @pyjit.freeze
+    def f(x, scale):
+        return x * scale
+
+    try:
+        f(pyjit.f32([1.0]), 0.5)
+    except TypeError as err:
+        assert "can not take a float" in str(err)
+    else:
+        assert False
+
+
+def test_scatter_is_rejected():
+    @pyjit.freeze
+    def f(x, dst):
+        x.scatter(dst, pyjit.u32([0, 1]))
+        return x + 1.0
+
+    dst = pyjit.f32([0.0, 0.0])
+    try:
+        f(pyjit.f32([1.0, 2.0]), dst)
+    except RuntimeError as err:
+        assert "scatter" in str(err)
+    else:
+        raise AssertionError("a scatter was recorded")
+    assert f.n_recordings == 0
+
+
+class Model:
+    def __init__(self, name, scale):
+        self.name = name
+        self.scale = scale
+        self.calls = 0
+
+    @pyjit.freeze
+    def apply(self, x):
+        self.calls += 1
+        return x * self.scale
+
+
+def test_frozen_method():
+    Model.apply.clear()
+    a = Model("a", pyjit.f32([2.0, 2.0]))
+    b = Model("b", pyjit.f32([3.0, 3.0]))
+    assert a.apply(pyjit.f32([1.0, 2.0])).to_list() == [2.0, 4.0]
+    # The object is neither part of the layout nor an input, its Vars are looked up
+    # again like captured variables.
+    assert b.apply(pyjit.f32([1.0, 2.0])).to_list() == [3.0, 6.0]
+    a.scale = pyjit.f32([4.0, 4.0])
+    assert a.apply(pyjit.f32([1.0, 2.0])).to_list() == [4.0, 8.0]
+    assert (a.calls, b.calls) == (1, 0)
+    assert Model.apply.n_recordings == 1
+    b.scale = pyjit.f32([5.0])
+    assert b.apply(pyjit.f32([1.0, 2.0])).to_list() == [5.0, 10.0]
+    assert b.calls == 1
+
+
+def test_replay_reuses_kernel():
+    if "cuda" not in pyjit.available_backends():
+        return
+
+    @pyjit.freeze
+    def f(x):
+        return x * 2.0 + 1.0
+
+    ctx = pyjit.Context()
+    ctx.set_backend("cuda")
+    with ctx:
+        pyjit.eval(f(pyjit.f32([1.0, 2.0])))
+        misses = pyjit.stats().cache_misses
+        y = f(pyjit.f32([3.0, 4.0]))
+        pyjit.eval(y)
+        assert pyjit.stats().cache_misses == misses
+        assert y.to_list() == [7.0, 9.0]
+
+
+if __name__ == "__main__":
+    test_cache_hit_skips_function()
+    test_layout_change_records_again()
+    test_rebound_global_is_replayed()
+    test_rebound_closure_variable_is_replayed()
+    test_scalars_are_part_of_the_layout()
+    test_unsupported_arguments_are_rejected()
+    test_scatter_is_rejected()
+    test_frozen_method()
+    test_replay_reuses_kernel()
@@ -0,0 +1,38 @@
+import threading
+import time
+
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+def test_eval_async():
+    x = pyjit.f32([1.0, 2.0]) + 1.0
+    fut = pyjit.eval_async(x)
+    assert fut.wait() is None
+    assert fut.done()
+    assert x.to_list() == [2.0, 3.0]
+
+
+def test_to_numpy_async():
+    x = pyjit.f32([1.0, 2.0]) * 3.0
+    assert list(x.to_numpy_async().wait()) == [3.0, 6.0]
+
+
+def test_done_while_waiting():
+    x = pyjit.f32([1.0]) + 1.0
+    fut = pyjit.eval_async(x)
+    waiter = threading.Thread(target=fut.wait)
+    waiter.start()
+    # `done` reports the state of the work, not whether `wait` holds the future.
+    deadline = time.time() + 10.0
+    while not fut.done():
+        assert time.time() < deadline, "done() never became true"
+        time.sleep(0.01)
+    waiter.join()
+
+
+if __name__ == "__main__":
+    test_eval_async()
+    test_to_numpy_async()
+    test_done_while_waiting()
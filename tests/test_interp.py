@@ -0,0 +1,147 @@
+import math
+import threading
+
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+def test_arithmetic():
+    x = pyjit.f32([1.0, 2.0, 3.0])
+    assert (x * 2.0 + 1.0).to_list() == [3.0, 5.0, 7.0]
+
+
+def test_transcendentals():
+    x = pyjit.f32([0.0, 3.0])
+    sin, cos = x.sin().to_list(), x.cos().to_list()
+    assert sin[0] == 0.0 and abs(sin[1] - math.sin(3.0)) < 1e-6
+    assert cos[0] == 1.0 and abs(cos[1] - math.cos(3.0)) < 1e-6
+    assert x.exp2().to_list() == [1.0, 8.0]
+    assert pyjit.f32([1.0, 8.0]).log2().to_list() == [0.0, 3.0]
+    assert pyjit.f32([4.0, 0.25]).rsqrt().to_list() == [0.5, 2.0]
+    assert pyjit.f32([4.0, 0.5]).rcp().to_list() == [0.25, 2.0]
+
+
+def test_bit_ops():
+    x = pyjit.u32([0b1100, 0b1010])
+    assert (x & 0b1000).to_list() == [0b1000, 0b1000]
+    assert (x | 0b0001).to_list() == [0b1101, 0b1011]
+    assert (x ^ 0b1111).to_list() == [0b0011, 0b0101]
+    assert x.shl(2).to_list() == [0b110000, 0b101000]
+    assert x.shr(2).to_list() == [0b11, 0b10]
+    assert getattr(x, "not")().to_list() == [0xFFFFFFF3, 0xFFFFFFF5]
+    assert x.popc().to_list() == [2, 2]
+    assert x.clz().to_list() == [28, 28]
+    assert x.ctz().to_list() == [2, 1]
+
+
+def test_casts():
+    assert pyjit.i32(pyjit.f32([-1.5, 2.7])).to_list() == [-1, 2]
+    assert pyjit.f32(pyjit.u8([0, 255])).to_list() == [0.0, 255.0]
+    assert pyjit.bool(pyjit.u32([0, 3])).to_list() == [False, True]
+    assert pyjit.u32(pyjit.bool([True, False])).to_list() == [1, 0]
+
+
+def test_bitcasts():
+    x = pyjit.f32([1.0, -2.0])
+    assert x.bitcast("u32").to_list() == [0x3F800000, 0xC0000000]
+    assert pyjit.u32([0x40400000]).bitcast("f32").to_list() == [3.0]
+
+
+def test_gather():
+    src = pyjit.f32([10.0, 20.0, 30.0])
+    idx = pyjit.u32([2, 0, 1])
+    assert src.gather(idx).to_list() == [30.0, 10.0, 20.0]
+    mask = pyjit.bool([True, False, True])
+    assert src.gather(idx, mask).to_list() == [30.0, 0.0, 20.0]
+
+
+def test_scatter_reduce():
+    dst = pyjit.u32([1, 1, 1])
+    pyjit.u32([1, 2, 4, 8]).scatter_reduce(dst, pyjit.u32([0, 2, 2, 0]))
+    assert dst.to_list() == [10, 1, 7]
+    pyjit.u32(5).scatter_reduce(dst, pyjit.u32([1, 2]), pyjit.bool([True, False]))
+    assert dst.to_list() == [10, 6, 7]
+
+
+def test_compress():
+    mask = pyjit.bool([False, True, True, False, True])
+    assert mask.compress().to_list() == [1, 2, 4]
+
+
+def test_index():
+    assert pyjit.index(4).to_list() == [0, 1, 2, 3]
+    assert (pyjit.index(3) * 2).to_list() == [0, 2, 4]
+
+
+def test_literals():
+    x = pyjit.f32(2.5, 3)
+    assert x.is_literal
+    assert x.to_list() == [2.5, 2.5, 2.5]
+    assert (pyjit.u32([1, 2]) + 3).to_list() == [4, 5]
+
+
+def test_released_values_are_dropped():
+    ctx = pyjit.Context()
+    ctx.set_backend("cpu")
+    with ctx:
+        x = pyjit.f32([1.0, 2.0])
+        y = x * 2.0
+        pyjit.eval(y)
+        assert pyjit.stats().live_variables > 0
+        del x, y
+        assert pyjit.stats().live_variables == 0
+        # Ids of released variables are reused, without their old values.
+        z = pyjit.f32([5.0, 6.0]) + 1.0
+        assert z.to_list() == [6.0, 7.0]
+
+
+def test_eval_target_runs_pending_scatter():
+    dst = pyjit.u32([0, 0, 0])
+    pyjit.u32([5, 7]).scatter(dst, pyjit.u32([0, 2]))
+    pyjit.eval(dst)
+    assert dst.to_list() == [5, 0, 7]
+
+
+def test_read_after_scatter_sees_written_values():
+    dst = pyjit.f32([1.0, 1.0])
+    pyjit.f32(3.0).scatter(dst, pyjit.u32([1]))
+    assert (dst + 1.0).to_list() == [2.0, 4.0]
+
+
+def test_contexts_interpret_concurrently():
+    errors = []
+
+    def work():
+        try:
+            ctx = pyjit.Context()
+            ctx.set_backend("cpu")
+            with ctx:
+                x = pyjit.f32(list(range(1000)))
+                assert (x * 2.0).to_list() == [2.0 * i for i in range(1000)]
+        except Exception as err:
+            errors.append(err)
+
+    threads = [threading.Thread(target=work) for _ in range(4)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+    assert not errors, errors
+
+
+if __name__ == "__main__":
+    test_arithmetic()
+    test_transcendentals()
+    test_bit_ops()
+    test_casts()
+    test_bitcasts()
+    test_gather()
+    test_scatter_reduce()
+    test_compress()
+    test_index()
+    test_literals()
+    test_released_values_are_dropped()
+    test_eval_target_runs_pending_scatter()
+    test_read_after_scatter_sees_written_values()
+    test_contexts_interpret_concurrently()
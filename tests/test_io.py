@@ -0,0 +1,132 @@
+import os
+import struct
+import tempfile
+import types
+
+import pyjit
+
+pyjit.set_backend("cpu")
+
+IDENTITY = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+
+
+def path(name):
+    return os.path.join(tempfile.mkdtemp(), name)
+
+
+def tensor():
+    return types.SimpleNamespace(data=pyjit.i32(list(range(6))), shape=[2, 3])
+
+
+def test_npy_round_trip():
+    for x in [pyjit.f32([1.0, -2.5]), pyjit.u8([1, 255]), pyjit.bool([True, False])]:
+        p = path("x.npy")
+        pyjit.save(p, x)
+        y = pyjit.load(p)
+        assert y.ty() == x.ty()
+        assert y.to_list() == x.to_list()
+
+
+def test_npy_tensor_round_trip():
+    p = path("t.npy")
+    pyjit.save(p, tensor())
+    t = pyjit.load(p)
+    assert t.shape == [2, 3]
+    assert t.data.to_list() == list(range(6))
+
+
+def test_npz_round_trip():
+    p = path("x.npz")
+    pyjit.save(p, {"a": pyjit.f64([1.0, 2.0]), "b": tensor()})
+    res = pyjit.load(p)
+    assert sorted(res) == ["a", "b"]
+    assert res["a"].to_list() == [1.0, 2.0]
+    assert res["b"].shape == [2, 3]
+
+
+def test_npz_single_value_is_arr_0():
+    p = path("x.npz")
+    pyjit.save(p, pyjit.u32([1, 2]))
+    assert pyjit.load(p)["arr_0"].to_list() == [1, 2]
+
+
+def test_native_round_trip():
+    p = path("x.pjit")
+    pyjit.save(p, {"a": pyjit.i16([-1, 2]), "b": tensor()})
+    res = pyjit.load(p)
+    assert res["a"].to_list() == [-1, 2]
+    assert res["b"].shape == [2, 3]
+    assert res["b"].data.to_list() == list(range(6))
+
+    pyjit.save(p, pyjit.f32([3.0]))
+    assert pyjit.load(p).to_list() == [3.0]
+
+
+def test_native_dict_named_arr_0_stays_a_dict():
+    p = path("x.pjit")
+    pyjit.save(p, {"arr_0": pyjit.f32([1.0])})
+    res = pyjit.load(p)
+    assert isinstance(res, dict)
+    assert res["arr_0"].to_list() == [1.0]
+
+
+def test_native_accel_round_trip():
+    desc = pyjit.AccelDesc()
+    vertices = pyjit.f32([1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0])
+    t0 = desc.add_triangles(vertices, pyjit.u32([0, 1, 2]))
+    desc.add_instance(t0, IDENTITY, 0)
+    p = path("scene.pjit")
+    pyjit.save(p, desc)
+    loaded = pyjit.load(p)
+    loaded.add_miss_group("__miss__ms", "")
+    loaded.add_hit_group("__closesthit__ch", "")
+    accel = pyjit.accel(loaded)
+    payload = accel.trace_ray(
+        [0, 0, 0, 0, 0], [[0.6, 0.1], 0.6, 0.0], [0.0, 0.0, 1.0], 0.001, 1000.0, 0.0
+    )
+    assert payload[0].to_list() == [1, 0]
+
+
+def test_npy_shape_mismatch_raises():
+    header = b"{'descr': '<f4', 'fortran_order': False, 'shape': (3,), }"
+    header += b" " * ((64 - (10 + len(header) + 1) % 64) % 64) + b"\n"
+    data = struct.pack("<2f", 1.0, 2.0)
+    p = path("bad.npy")
+    with open(p, "wb") as f:
+        f.write(b"\x93NUMPY\x01\x00" + struct.pack("<H", len(header)) + header + data)
+    try:
+        pyjit.load(p)
+    except Exception as err:
+        assert "shape" in str(err)
+    else:
+        raise AssertionError("a truncated .npy file was loaded")
+
+
+def test_numpy_interop():
+    try:
+        import numpy as np
+    except ImportError:
+        return
+    p = path("x.npy")
+    pyjit.save(p, pyjit.f32([1.0, 2.0]))
+    assert np.load(p).tolist() == [1.0, 2.0]
+    np.save(p, np.arange(6, dtype=np.int64).reshape(2, 3))
+    t = pyjit.load(p)
+    assert t.shape == [2, 3]
+    assert t.data.to_list() == list(range(6))
+
+    p = path("x.npz")
+    np.savez_compressed(p, a=np.array([1.5], dtype=np.float32))
+    assert pyjit.load(p)["a"].to_list() == [1.5]
+
+
+if __name__ == "__main__":
+    test_npy_round_trip()
+    test_npy_tensor_round_trip()
+    test_npz_round_trip()
+    test_npz_single_value_is_arr_0()
+    test_native_round_trip()
+    test_native_dict_named_arr_0_stays_a_dict()
+    test_native_accel_round_trip()
+    test_npy_shape_mismatch_raises()
+    test_numpy_interop()
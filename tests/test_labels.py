@@ -0,0 +1,48 @@
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+def raises(exc, f):
+    try:
+        f()
+    except exc as err:
+        return str(err)
+    raise AssertionError(f"{exc.__name__} was not raised")
+
+
+def test_operand_error_names_label():
+    x = pyjit.f32([1.0, 2.0])
+    x.label = "radiance"
+    msg = raises(TypeError, lambda: x + "not a number")
+    assert "radiance" in msg
+
+
+def test_bitcast_error_names_label():
+    x = pyjit.u32([1, 2])
+    x.label = "seed"
+    assert "seed" in raises(TypeError, lambda: x.bitcast("f16"))
+
+
+def test_context_error_names_labels():
+    a = pyjit.f32([1.0])
+    a.label = "a"
+    ctx = pyjit.Context()
+    ctx.set_backend("cpu")
+    with ctx:
+        b = pyjit.f32([2.0])
+        b.label = "b"
+    msg = raises(ValueError, lambda: a + b)
+    assert "a" in msg and "b" in msg
+
+
+def test_unlabeled_error_names_id():
+    x = pyjit.f32([1.0])
+    assert f"#{x.id}" in raises(TypeError, lambda: x + "not a number")
+
+
+if __name__ == "__main__":
+    test_operand_error_names_label()
+    test_bitcast_error_names_label()
+    test_context_error_names_labels()
+    test_unlabeled_error_names_id()
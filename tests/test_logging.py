@@ -0,0 +1,85 @@
+import logging
+
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+class Records(logging.Handler):
+    def __init__(self, name):
+        super().__init__(logging.DEBUG)
+        self.records = []
+        self.logger = logging.getLogger(name)
+
+    def emit(self, record):
+        self.records.append(record)
+
+    def __enter__(self):
+        self.logger.addHandler(self)
+        return self.records
+
+    def __exit__(self, *args):
+        self.logger.removeHandler(self)
+
+
+def test_rust_records_reach_python_logging():
+    pyjit.set_log_level("info")
+    with Records("pyjit") as records:
+        pyjit.set_backend("cpu")
+    assert [(r.name, r.levelno, r.getMessage()) for r in records] == [
+        ("pyjit.backend", logging.INFO, "Using the cpu backend")
+    ]
+
+
+def test_set_log_level_filters_records():
+    pyjit.set_log_level(logging.WARNING)
+    try:
+        with Records("pyjit") as records:
+            pyjit.set_backend("cpu")
+        assert records == []
+    finally:
+        pyjit.set_log_level("info")
+
+
+def test_set_log_level_of_subsystem():
+    pyjit.set_log_level("debug", "launch")
+    for name in ("rjit.launch", "pyjit.launch"):
+        assert logging.getLogger(name).level == logging.DEBUG
+    pyjit.set_log_level(logging.ERROR, "launch")
+    for name in ("rjit.launch", "pyjit.launch"):
+        assert logging.getLogger(name).level == logging.ERROR
+
+
+def test_unknown_target_raises():
+    try:
+        pyjit.set_log_level("debug", "network")
+    except ValueError as err:
+        assert "Unknown log target network" in str(err)
+    else:
+        raise AssertionError("an unknown log target was accepted")
+
+
+def test_launch_records():
+    if "cuda" not in pyjit.available_backends():
+        return
+    ctx = pyjit.Context()
+    ctx.set_backend("cuda")
+    pyjit.set_log_level("debug", "launch")
+    with Records("pyjit.launch") as records, ctx:
+        pyjit.eval(pyjit.f32([1.0, 2.0]) * 3.0)
+    assert len(records) >= 1
+    for record in records:
+        assert len(record.kernel_hash) == 16
+        assert record.compile_time >= 0.0 and record.run_time >= 0.0
+    pyjit.set_log_level("warning", "launch")
+    with Records("pyjit.launch") as records, ctx:
+        pyjit.eval(pyjit.f32([1.0, 2.0]) * 5.0)
+    assert records == []
+
+
+if __name__ == "__main__":
+    test_rust_records_reach_python_logging()
+    test_set_log_level_filters_records()
+    test_set_log_level_of_subsystem()
+    test_unknown_target_raises()
+    test_launch_records()
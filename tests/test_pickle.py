@@ -0,0 +1,56 @@
+import copy
+import pickle
+
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+def test_pickle_round_trip():
+    for x in [
+        pyjit.f32([1.0, 2.5, -3.0]),
+        pyjit.u32([1, 2, 3]),
+        pyjit.i64([-1, 0, 1]),
+        pyjit.bool([True, False]),
+    ]:
+        y = pickle.loads(pickle.dumps(x))
+        assert y.ty() == x.ty()
+        assert y.to_list() == x.to_list()
+
+
+def test_pickle_evaluates_pending():
+    x = pyjit.f32([1.0, 2.0]) * 2.0
+    assert pickle.loads(pickle.dumps(x)).to_list() == [2.0, 4.0]
+
+
+def test_copy_shares_variable():
+    x = pyjit.u32([0, 0])
+    y = copy.copy(x)
+    assert y.id == x.id
+    pyjit.u32(5).scatter(x, 1)
+    assert y.to_list() == [0, 5]
+
+
+def test_deepcopy_is_independent():
+    x = pyjit.u32([0, 0])
+    y = copy.deepcopy(x)
+    assert y.id != x.id
+    pyjit.u32(5).scatter(x, 1)
+    assert x.to_list() == [0, 5]
+    assert y.to_list() == [0, 0]
+
+
+def test_deepcopy_of_structure():
+    tree = {"a": [pyjit.f32([1.0]), 2], "b": pyjit.i32([3])}
+    res = copy.deepcopy(tree)
+    assert res["a"][0].to_list() == [1.0]
+    assert res["a"][1] == 2
+    assert res["b"].to_list() == [3]
+
+
+if __name__ == "__main__":
+    test_pickle_round_trip()
+    test_pickle_evaluates_pending()
+    test_copy_shares_variable()
+    test_deepcopy_is_independent()
+    test_deepcopy_of_structure()
@@ -0,0 +1,66 @@
+import json
+import os
+import tempfile
+
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+def profile(f):
+    with tempfile.TemporaryDirectory() as dir:
+        path = os.path.join(dir, "trace.json")
+        with pyjit.Profile(path):
+            f()
+        with open(path) as file:
+            return json.load(file)["traceEvents"]
+
+
+def test_context_manager_writes_chrome_trace():
+    events = profile(lambda: (pyjit.f32([1.0, 2.0]) * 2.0).to_list())
+    transfers = [e for e in events if e["cat"] == "transfer"]
+    assert [e["name"] for e in transfers] == ["to_host"]
+    for event in events:
+        assert event["ph"] == "X"
+        assert event["pid"] == os.getpid()
+        assert event["ts"] >= 0 and event["dur"] >= 0
+        assert event["args"]["synthetic"] in (True, False)
+    assert transfers[0]["args"] == {"synthetic": False}
+
+
+def test_stop_without_start_raises():
+    try:
+        pyjit.profile_stop(os.path.join(tempfile.gettempdir(), "never_written.json"))
+    except RuntimeError as err:
+        assert "not been started" in str(err)
+    else:
+        raise AssertionError("profile_stop without profile_start succeeded")
+
+
+def test_launches_carry_kernel_args():
+    if "cuda" not in pyjit.available_backends():
+        return
+    ctx = pyjit.Context()
+    ctx.set_backend("cuda")
+
+    def run():
+        with ctx:
+            pyjit.eval(pyjit.f32([1.0, 2.0]) * 3.0)
+
+    events = profile(run)
+    assert [e["cat"] for e in events if e["cat"] in ("trace", "eval")] == ["eval", "trace"]
+    launches = [e for e in events if e["cat"] == "launch"]
+    compiles = [e for e in events if e["cat"] == "compile"]
+    assert len(launches) == len(compiles) >= 1
+    for launch, compile in zip(launches, compiles):
+        args = launch["args"]
+        assert args["synthetic"] is True
+        assert args["kernel_hash"] == launch["name"] == compile["name"]
+        assert args["compile_time"] >= 0.0 and args["run_time"] >= 0.0
+        assert compile["args"] == args
+
+
+if __name__ == "__main__":
+    test_context_manager_writes_chrome_trace()
+    test_stop_without_start_raises()
+    test_launches_carry_kernel_args()
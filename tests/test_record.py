@@ -0,0 +1,85 @@
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+def recording():
+    x = pyjit.f32([1.0, 2.0, 3.0])
+    y = pyjit.f32([4.0, 5.0, 6.0])
+    return pyjit.record(x * 2.0 + y, data=True)
+
+
+def test_record_counts_inputs():
+    assert recording().n_inputs == 2
+
+
+def test_replay_with_new_inputs():
+    (z,) = recording().replay(pyjit.f32([0.0, 1.0, 2.0]), pyjit.f32([1.0, 1.0, 1.0]))
+    assert z.to_list() == [1.0, 3.0, 5.0]
+
+
+def test_replay_recorded_data():
+    (z,) = recording().replay()
+    assert z.to_list() == [6.0, 9.0, 12.0]
+
+
+def test_replay_without_data_needs_inputs():
+    x = pyjit.f32([1.0, 2.0])
+    rec = pyjit.record(x + 1.0)
+    try:
+        rec.replay()
+    except RuntimeError as err:
+        assert "inputs have to be passed" in str(err)
+    else:
+        assert False
+
+
+def test_replay_checks_input_count():
+    try:
+        recording().replay(pyjit.f32([0.0, 1.0, 2.0]))
+    except RuntimeError as err:
+        assert "Expected 2 inputs, got 1!" in str(err)
+    else:
+        assert False
+
+
+def test_replay_checks_input_type():
+    try:
+        recording().replay(pyjit.u32([0, 1, 2]), pyjit.f32([1.0, 1.0, 1.0]))
+    except RuntimeError as err:
+        assert "Input 0 has to be of type" in str(err)
+    else:
+        assert False
+
+
+def test_json_round_trip():
+    rec = recording()
+    copy = pyjit.Recording.from_json(rec.to_json())
+    assert copy == rec
+    (z,) = copy.replay()
+    assert z.to_list() == [6.0, 9.0, 12.0]
+
+
+def test_bytes_round_trip():
+    rec = recording()
+    copy = pyjit.Recording.from_bytes(rec.to_bytes())
+    assert copy == rec
+    (z,) = copy.replay()
+    assert z.to_list() == [6.0, 9.0, 12.0]
+
+
+def test_recordings_differ():
+    x = pyjit.f32([1.0, 2.0])
+    assert not pyjit.record(x + 1.0) == pyjit.record(x * 1.0)
+
+
+if __name__ == "__main__":
+    test_record_counts_inputs()
+    test_replay_with_new_inputs()
+    test_replay_recorded_data()
+    test_replay_without_data_needs_inputs()
+    test_replay_checks_input_count()
+    test_replay_checks_input_type()
+    test_json_round_trip()
+    test_bytes_round_trip()
+    test_recordings_differ()
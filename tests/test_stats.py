@@ -0,0 +1,76 @@
+import pyjit
+
+pyjit.set_backend("cpu")
+
+FIELDS = [
+    "kernel_launches",
+    "cache_hits",
+    "cache_misses",
+    "compile_time",
+    "run_time",
+    "allocated_bytes",
+    "peak_allocated_bytes",
+    "live_variables",
+]
+
+
+def test_fields():
+    stats = pyjit.stats()
+    for field in FIELDS:
+        assert getattr(stats, field) >= 0
+    assert repr(stats).startswith("Stats {")
+
+
+def test_contexts_count_separately():
+    a, b = pyjit.Context(), pyjit.Context()
+    for ctx in (a, b):
+        ctx.set_backend("cpu")
+    with a:
+        x = pyjit.f32([1.0, 2.0, 3.0]) * 2.0
+        assert pyjit.stats().live_variables > 0
+    with b:
+        assert pyjit.stats().live_variables == 0
+    del x
+    with a:
+        assert pyjit.stats().live_variables == 0
+
+
+def test_reset_stats():
+    pyjit.eval(pyjit.f32([1.0, 2.0]) + 1.0)
+    pyjit.reset_stats()
+    stats = pyjit.stats()
+    assert stats.kernel_launches == 0
+    assert stats.cache_hits == 0
+    assert stats.cache_misses == 0
+    assert stats.compile_time == 0.0
+    assert stats.run_time == 0.0
+
+
+def test_launches_and_cache_lookups():
+    if "cuda" not in pyjit.available_backends():
+        return
+    a, b = pyjit.Context(), pyjit.Context()
+    for ctx in (a, b):
+        ctx.set_backend("cuda")
+    with a:
+        pyjit.reset_stats()
+        pyjit.eval(pyjit.f32([1.0, 2.0]) * 4.0)
+        pyjit.eval(pyjit.f32([1.0, 2.0]) * 4.0)
+        stats = pyjit.stats()
+        assert stats.kernel_launches == 2
+        assert stats.cache_hits + stats.cache_misses == 2
+        assert stats.cache_hits >= 1
+    with b:
+        # Launches and lookups of another context do not count here.
+        pyjit.reset_stats()
+        pyjit.eval(pyjit.f32([1.0, 2.0]) * 4.0)
+        stats = pyjit.stats()
+        assert stats.kernel_launches == 1
+        assert stats.cache_hits + stats.cache_misses == 1
+
+
+if __name__ == "__main__":
+    test_fields()
+    test_contexts_count_separately()
+    test_reset_stats()
+    test_launches_and_cache_lookups()
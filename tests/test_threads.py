@@ -0,0 +1,34 @@
+import threading
+
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+def test_concurrent_eval():
+    n_threads = 8
+    results = [None] * n_threads
+    errors = []
+
+    def work(i):
+        try:
+            for _ in range(20):
+                x = pyjit.f32([float(i), 1.0, 2.0]) * 2.0 + 1.0
+                pyjit.eval(x)
+                results[i] = x.to_list()
+        except Exception as err:
+            errors.append(err)
+
+    threads = [threading.Thread(target=work, args=(i,)) for i in range(n_threads)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+
+    assert not errors, errors
+    for i, res in enumerate(results):
+        assert res == [2.0 * i + 1.0, 3.0, 5.0]
+
+
+if __name__ == "__main__":
+    test_concurrent_eval()
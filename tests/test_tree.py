@@ -0,0 +1,67 @@
+import dataclasses
+
+import pyjit
+
+pyjit.set_backend("cpu")
+
+
+@dataclasses.dataclass(frozen=True)
+class Hit:
+    t: pyjit.Var
+    name: str
+
+
+def test_select_scalars():
+    mask = pyjit.bool([True, False, True])
+    res = pyjit.select(mask, 1.0, 2.0)
+    assert res.to_list() == [1.0, 2.0, 1.0]
+    assert pyjit.select(mask, None, None) is None
+    assert pyjit.select(False, 1.0, 2.0) == 2.0
+
+
+def test_select_mixed_leaves():
+    mask = pyjit.bool([True, False])
+    res = pyjit.select(mask, {"x": pyjit.f32([1.0, 2.0]), "n": 1}, {"x": 0.0, "n": 2})
+    assert res["x"].to_list() == [1.0, 0.0]
+    assert res["n"].to_list() == [1, 2]
+
+
+def test_select_unequal_objects_raises():
+    mask = pyjit.bool([True])
+    try:
+        pyjit.select(mask, "a", "b")
+    except TypeError:
+        pass
+    else:
+        raise AssertionError("selecting between strings did not raise")
+
+
+def test_frozen_dataclass():
+    mask = pyjit.bool([True, False])
+    a = Hit(pyjit.f32([1.0, 2.0]), "hit")
+    b = Hit(pyjit.f32([3.0, 4.0]), "hit")
+    res = pyjit.select(mask, a, b)
+    assert isinstance(res, Hit)
+    assert res.t.to_list() == [1.0, 4.0]
+    assert res.name == "hit"
+
+
+def test_gather_keeps_scalars():
+    res = pyjit.gather((pyjit.f32([1.0, 2.0, 3.0]), "label"), pyjit.u32([2, 0]))
+    assert res[0].to_list() == [3.0, 1.0]
+    assert res[1] == "label"
+
+
+def test_switch_scalar_results():
+    index = pyjit.u32([1, 0, 1])
+    res = pyjit.switch(index, [lambda: 1.0, lambda: 2.0])
+    assert res.to_list() == [2.0, 1.0, 2.0]
+
+
+if __name__ == "__main__":
+    test_select_scalars()
+    test_select_mixed_leaves()
+    test_select_unequal_objects_raises()
+    test_frozen_dataclass()
+    test_gather_keeps_scalars()
+    test_switch_scalar_results()
@@ -0,0 +1,114 @@
+import pyjit
+
+pyjit.set_backend("cpu")
+
+IDENTITY = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+VERTICES = [1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0]
+
+
+def triangles(desc, indices=[0, 1, 2]):
+    return desc.add_triangles(pyjit.f32(VERTICES), pyjit.u32(indices))
+
+
+def spheres(desc):
+    return desc.add_spheres(pyjit.f32([0.0, 0.0, 1.0]), pyjit.f32([0.5]))
+
+
+def desc_with(geometry=triangles, instance_geometry=None, hit_group=0, **intersection):
+    desc = pyjit.AccelDesc()
+    g = geometry(desc)
+    desc.add_instance(g if instance_geometry is None else instance_geometry, IDENTITY, hit_group)
+    desc.add_miss_group("__miss__ms", "")
+    desc.add_hit_group("__closesthit__ch", "", **intersection)
+    return desc
+
+
+def assert_invalid(desc, message):
+    for validate in (desc.validate, lambda: pyjit.accel(desc)):
+        try:
+            validate()
+        except ValueError as err:
+            assert message in str(err), str(err)
+        else:
+            raise AssertionError(f"expected a ValueError containing {message!r}")
+
+
+def test_valid_desc():
+    desc_with().validate()
+
+
+def test_index_out_of_range():
+    desc = desc_with(lambda d: triangles(d, [0, 1, 3]))
+    assert_invalid(desc, "Geometry 0: index 3 is out of range for 3 vertices!")
+
+
+def test_partial_triangle():
+    desc = desc_with(lambda d: triangles(d, [0, 1]))
+    assert_invalid(desc, "Geometry 0: 2 indices are not a multiple of 3!")
+
+
+def test_missing_geometry():
+    desc = desc_with(instance_geometry=1)
+    assert_invalid(desc, "Instance 0: geometry 1 does not exist, there are 1 geometries!")
+
+
+def test_missing_hit_group():
+    desc = desc_with(hit_group=1)
+    assert_invalid(desc, "Instance 0: hit group 1 does not exist, there are 1 hit groups!")
+
+
+def test_mismatched_intersection():
+    assert_invalid(
+        desc_with(spheres),
+        "Instance 0: geometry 0 consists of spheres, but hit group 0 has no intersection",
+    )
+    assert_invalid(
+        desc_with(spheres, intersection_builtin="linear"),
+        "but hit group 0 has the built-in linear curve intersection!",
+    )
+    desc_with(spheres, intersection_builtin="spheres").validate()
+
+
+def aabbs(bbox_min):
+    return lambda d: d.add_aabbs(pyjit.f32(bbox_min), pyjit.f32([1.0, 1.0, 1.0]))
+
+
+def test_inverted_aabb():
+    custom = {"intersection_entry_point": "__intersection__is", "intersection_asm": ""}
+    assert_invalid(
+        desc_with(aabbs([0.0, 2.0, 0.0]), **custom),
+        "Geometry 0: box 0 has bbox_min 2 above bbox_max 1 along y!",
+    )
+    desc_with(aabbs([0.0, 1.0, 0.0]), **custom).validate()
+
+
+def test_cpu_backend_rejects_aabbs():
+    custom = {"intersection_entry_point": "__intersection__is", "intersection_asm": ""}
+    try:
+        pyjit.accel(desc_with(aabbs([0.0, 0.0, 0.0]), **custom))
+    except RuntimeError as err:
+        assert "can not run the intersection programs" in str(err)
+    else:
+        raise AssertionError("the cpu backend built AABBs it can not intersect")
+
+
+def test_program_without_entry_point():
+    for program in ("any_hit", "intersection"):
+        try:
+            pyjit.AccelDesc().add_hit_group("__closesthit__ch", "", **{f"{program}_asm": ""})
+        except ValueError as err:
+            assert "needs both its assembly and its entry point" in str(err)
+        else:
+            raise AssertionError(f"{program} program without entry point was accepted")
+
+
+if __name__ == "__main__":
+    test_valid_desc()
+    test_index_out_of_range()
+    test_partial_triangle()
+    test_missing_geometry()
+    test_missing_hit_group()
+    test_mismatched_intersection()
+    test_inverted_aabb()
+    test_cpu_backend_rejects_aabbs()
+    test_program_without_entry_point()
@@ -0,0 +1,46 @@
+import pyjit
+
+# Same scene as trace_ray.py, traced by the software fallback of the cpu backend.
+pyjit.set_backend("cpu")
+
+indices = pyjit.u32([0, 1, 2])
+vertices = pyjit.f32([1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0])
+
+desc = pyjit.AccelDesc()
+t0 = desc.add_triangles(vertices, indices)
+desc.add_instance(t0, [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0], 0)
+
+# The programs are not run by the cpu backend, it fills in the payload itself.
+desc.add_miss_group("__miss__ms", "")
+desc.add_hit_group("__closesthit__ch", "")
+
+accel: pyjit.Var = pyjit.accel(desc)
+
+payload: list[pyjit.Var] = accel.trace_ray(
+    [0, 0, 0, 0, 0],
+    [[0.6, 0.1], 0.6, 0.0],
+    [0.0, 0.0, [1.0, 1.0]],
+    0.001,
+    1000.0,
+    0.0,
+)
+
+valid = pyjit.bool(payload[0])
+primitive_idx = payload[1]
+instance_id = payload[2]
+u = payload[3].bitcast("f32")
+v = payload[4].bitcast("f32")
+
+print(f"{valid=}")
+print(f"{primitive_idx=}")
+print(f"{instance_id=}")
+print(f"{u=}")
+print(f"{v=}")
+
+# The first ray hits the triangle at (0.6, 0.6, 1.0), the second one passes it.
+assert valid.to_list() == [True, False]
+assert primitive_idx.to_list() == [0, 0]
+assert instance_id.to_list() == [0, 0]
+u, v = u.to_list(), v.to_list()
+assert abs(u[0] - 0.4) < 1e-6 and abs(v[0] - 0.2) < 1e-6
+assert u[1] == 0.0 and v[1] == 0.0
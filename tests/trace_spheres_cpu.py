@@ -0,0 +1,53 @@
+import pyjit
+
+# Spheres and curves use built-in intersection modules, traced here by the cpu backend.
+pyjit.set_backend("cpu")
+
+desc = pyjit.AccelDesc()
+spheres = desc.add_spheres([0.0, 0.0, 2.0], [0.5])
+curves = desc.add_curves(
+    [2.0, -1.0, 2.0, 2.0, 0.0, 2.0, 2.0, 1.0, 2.0], [0.1, 0.1, 0.1], [0, 1], "linear"
+)
+
+identity = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+desc.add_miss_group("__miss__ms", "")
+sphere_group = desc.add_hit_group("__closesthit__ch", "", intersection_builtin="spheres")
+curve_group = desc.add_hit_group("__closesthit__ch", "", intersection_builtin="linear")
+desc.add_instance(spheres, identity, sphere_group)
+desc.add_instance(curves, identity, curve_group)
+
+accel: pyjit.Var = pyjit.accel(desc)
+
+# The first ray hits the sphere, the second the upper curve segment, the third nothing.
+payload: list[pyjit.Var] = accel.trace_ray(
+    [0, 0, 0, 0, 0],
+    [[0.0, 2.0, 4.0], [0.0, 0.5, 0.0], 0.0],
+    [0.0, 0.0, 1.0],
+    0.001,
+    1000.0,
+    0.0,
+)
+
+valid = pyjit.bool(payload[0])
+primitive_idx = payload[1]
+instance_id = payload[2]
+u = payload[3].bitcast("f32")
+v = payload[4].bitcast("f32")
+
+print(f"{valid=}")
+print(f"{primitive_idx=}")
+print(f"{instance_id=}")
+print(f"{u=}")
+print(f"{v=}")
+
+assert valid.to_list() == [True, True, False]
+# The sphere is the only primitive of instance 0, the curve segment starting at
+# vertex 1 is primitive 1 of instance 1. The missing ray keeps the initial payload.
+assert primitive_idx.to_list() == [0, 1, 0]
+assert instance_id.to_list() == [0, 1, 0]
+u, v = u.to_list(), v.to_list()
+# Spheres: the longitude and colatitude of the hit point (0, 0, -0.5) relative to
+# the center, scaled to [0, 1]. Curves: the parameter within the segment, and 0.
+assert abs(u[0] - 0.5) < 1e-6 and abs(v[0] - 1.0) < 1e-6
+assert abs(u[1] - 0.5) < 1e-3 and v[1] == 0.0
+assert u[2] == 0.0 and v[2] == 0.0